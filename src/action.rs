@@ -1,40 +1,66 @@
 // Copyright 2023 Martin Pool
 
+use std::fmt;
 use std::str::FromStr;
+use std::sync::LazyLock;
 
-use eyre::{bail, eyre, WrapErr};
 use regex::Regex;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::Error;
+use crate::glob::Pattern;
+
+static SERVICE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9-]+$").unwrap());
+
+/// The action part of a glob, in which `*` and `?` are wildcards.
+static ACTION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[a-zA-Z0-9*?]+$").unwrap());
 
 /// Some kind of "Action" pattern: a wildcard, a literal, or a glob.
+///
+/// Globs are checked when they're parsed, which happens when a policy is loaded, so
+/// that an invalid glob is reported as an error rather than never matching.
+///
+/// In the action part of a glob, `*` matches any sequence of characters and `?`
+/// matches any single character. Matching is case-insensitive.
+#[derive(Debug, Clone)]
 pub enum ActionGlob {
     Star,
     Literal(String),
-    Pattern(Regex),
+    Pattern {
+        /// The glob as written in the policy, e.g. `s3:Get*`.
+        glob: String,
+        /// The glob in lowercase, to match lowercased actions.
+        pattern: Pattern,
+    },
 }
 
 impl FromStr for ActionGlob {
-    type Err = eyre::Error;
+    type Err = Error;
 
     fn from_str(action_str: &str) -> Result<Self, Self::Err> {
         if action_str == "*" {
             return Ok(ActionGlob::Star);
         }
+        let invalid = |reason: String| Error::InvalidAction {
+            action: action_str.to_owned(),
+            reason,
+        };
         let (service, action) = action_str
             .split_once(':')
-            .ok_or_else(|| eyre!("no colon in action pattern {action_str:?}"))?;
-        let service_re = Regex::new(r"^[a-zA-Z0-9]+$").unwrap();
-        if !service_re.is_match(service) {
-            bail!("invalid service {service:?}");
+            .ok_or_else(|| invalid("no colon between the service and action".to_owned()))?;
+        if !SERVICE_RE.is_match(service) {
+            return Err(invalid(format!("invalid service {service:?}")));
         }
-        let valid_action = Regex::new(r"^[a-zA-Z0-9*]+$").unwrap();
-        if !valid_action.is_match(action) {
-            bail!("invalid action glob {action:?}");
-        } else if action.contains('*') {
-            let action_re_str = format!("^(?i){service}:{}$", action.replace('*', ".*"));
-            let action_re = Regex::new(&action_re_str).wrap_err_with(|| {
-                "failed to compile action regexp {action_re_str:?} from {action:?}"
-            })?;
-            Ok(ActionGlob::Pattern(action_re))
+        if !ACTION_RE.is_match(action) {
+            Err(invalid(format!("invalid action glob {action:?}")))
+        } else if action.contains(['*', '?']) {
+            Ok(ActionGlob::Pattern {
+                glob: action_str.to_owned(),
+                pattern: Pattern::new(&action_str.to_ascii_lowercase()),
+            })
         } else {
             Ok(ActionGlob::Literal(action_str.to_owned()))
         }
@@ -46,7 +72,59 @@ impl ActionGlob {
         match self {
             ActionGlob::Star => true,
             ActionGlob::Literal(a) => a.eq_ignore_ascii_case(action),
-            ActionGlob::Pattern(re) => re.is_match(action),
+            ActionGlob::Pattern { pattern, .. } => pattern.matches(&action.to_ascii_lowercase()),
+        }
+    }
+
+    /// The glob as written in the policy.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ActionGlob::Star => "*",
+            ActionGlob::Literal(glob) | ActionGlob::Pattern { glob, .. } => glob,
         }
     }
 }
+
+impl fmt::Display for ActionGlob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Globs are equal if they're written the same way.
+impl PartialEq for ActionGlob {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ActionGlob {}
+
+impl Serialize for ActionGlob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ActionGlob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// In JSON an action glob is just a string.
+impl JsonSchema for ActionGlob {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
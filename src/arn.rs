@@ -0,0 +1,188 @@
+// Copyright 2023 Martin Pool
+
+//! Amazon Resource Names, and matching them against patterns in policies.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference-arns.html>.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::context::Context;
+use crate::error::Error;
+use crate::glob::{GlobChar, Pattern};
+use crate::variable::expand;
+
+/// An Amazon Resource Name, split into its segments.
+///
+/// For example `arn:aws:iam::111122223333:user/mateo`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Arn {
+    /// The partition, usually "aws".
+    pub partition: String,
+    /// The service namespace, e.g. "s3".
+    pub service: String,
+    /// The region, which is empty for global services.
+    pub region: String,
+    /// The owning account id, which is empty for some services such as S3.
+    pub account_id: String,
+    /// The service-specific resource identifier, which may contain colons
+    /// and slashes.
+    pub resource: String,
+}
+
+impl FromStr for Arn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidArn {
+            arn: s.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let parts = s.splitn(6, ':').collect::<Vec<&str>>();
+        if parts.len() != 6 {
+            return Err(invalid("does not have six colon-separated segments"));
+        }
+        if parts[0] != "arn" {
+            return Err(invalid("does not start with \"arn:\""));
+        }
+        Ok(Arn {
+            partition: parts[1].to_owned(),
+            service: parts[2].to_owned(),
+            region: parts[3].to_owned(),
+            account_id: parts[4].to_owned(),
+            resource: parts[5].to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for Arn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "arn:{}:{}:{}:{}:{}",
+            self.partition, self.service, self.region, self.account_id, self.resource
+        )
+    }
+}
+
+/// Test whether a resource ARN matches a pattern from a policy `Resource` element.
+///
+/// A pattern of just `*` matches any resource. Otherwise, the pattern and the ARN
+/// are each split into their six colon-separated segments and each segment is matched
+/// separately, so a wildcard never extends across segments, except within the final
+/// resource segment which may itself contain colons. Within a segment `*` matches
+/// any sequence of characters and `?` matches any one character.
+///
+/// Matching is case-sensitive.
+pub fn arn_glob_matches(pattern: &str, arn: &str) -> bool {
+    arn_pattern_matches(&Pattern::new(pattern), arn)
+}
+
+/// Test whether a resource ARN matches a parsed pattern, as for [arn_glob_matches].
+pub fn arn_pattern_matches(pattern: &Pattern, arn: &str) -> bool {
+    if pattern.is_star() {
+        return true;
+    }
+    let mut pattern_parts: Vec<Pattern> = vec![Pattern::default()];
+    for c in &pattern.0 {
+        if *c == GlobChar::Literal(':') && pattern_parts.len() < 6 {
+            pattern_parts.push(Pattern::default());
+        } else {
+            pattern_parts.last_mut().unwrap().0.push(*c);
+        }
+    }
+    let arn_parts = arn.splitn(6, ':').collect::<Vec<&str>>();
+    pattern_parts.len() == 6
+        && arn_parts.len() == 6
+        && pattern_parts
+            .iter()
+            .zip(arn_parts.iter())
+            .all(|(p, a)| p.matches(a))
+}
+
+/// A pattern from a policy `Resource` or `NotResource` element: either `*`, or an ARN
+/// that may contain wildcards and policy variables, such as
+/// `arn:aws:s3:::home/${aws:username}/*`.
+///
+/// Patterns are checked when they're parsed, which happens when a policy is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArnPattern {
+    /// The pattern as written in the policy.
+    text: String,
+    /// The pattern with any variables left as literal text.
+    pattern: Pattern,
+}
+
+impl FromStr for ArnPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s != "*" {
+            Arn::from_str(s)?;
+        }
+        Ok(ArnPattern {
+            text: s.to_owned(),
+            pattern: Pattern::new(s),
+        })
+    }
+}
+
+impl ArnPattern {
+    /// The pattern as written in the policy.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Expand any policy variables in the pattern, or if `expand_variables` is false,
+    /// treat them as literal text.
+    ///
+    /// Returns None if a variable can't be resolved, as for [expand].
+    pub fn expand(&self, context: &Context, expand_variables: bool) -> Option<Cow<'_, Pattern>> {
+        if expand_variables && self.text.contains("${") {
+            expand(&self.text, context).map(Cow::Owned)
+        } else {
+            Some(Cow::Borrowed(&self.pattern))
+        }
+    }
+}
+
+impl fmt::Display for ArnPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Serialize for ArnPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for ArnPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// In JSON an ARN pattern is just a string.
+impl JsonSchema for ArnPattern {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
@@ -0,0 +1,450 @@
+// Copyright 2023 Martin Pool
+
+//! Simulate `sts:AssumeRole`, including chains in which each role session assumes
+//! the next role.
+//!
+//! See <https://docs.aws.amazon.com/STS/latest/APIReference/API_AssumeRole.html>
+//! and <https://docs.aws.amazon.com/IAM/latest/UserGuide/id_session-tags.html>.
+
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::arn::Arn;
+use crate::context::{self, Context};
+use crate::decision::Decision;
+use crate::error::{Error, ReferenceKind, Result};
+use crate::policy::SessionPolicy;
+use crate::principal::{AssumedRole, Principal};
+use crate::request::Request;
+use crate::role::Role;
+use crate::scenario::Scenario;
+use crate::tag::Tag;
+
+/// The action to assume a role.
+pub const ASSUME_ROLE: &str = "sts:AssumeRole";
+
+/// The action to pass session tags when assuming a role.
+pub const TAG_SESSION: &str = "sts:TagSession";
+
+/// The session name used if none is given.
+pub const DEFAULT_SESSION_NAME: &str = "iamthat";
+
+/// The default, and for role chaining the maximum, session duration in seconds.
+const ONE_HOUR: u32 = 3600;
+
+/// The minimum session duration in seconds.
+const MIN_DURATION: u32 = 900;
+
+/// The parameters of one call to `sts:AssumeRole`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct AssumeRoleRequest {
+    /// The role to assume, which must be in the scenario: either its ARN, such as
+    /// `arn:aws:iam::111122223333:role/ci/Deploy`, or for a role in the caller's
+    /// account, just its name.
+    pub role: String,
+
+    /// The session name, which becomes the last part of the session ARN.
+    #[serde(default)]
+    pub role_session_name: Option<String>,
+
+    /// Tags to attach to the new session.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+
+    /// Keys of the new session tags that are passed on to any later sessions in a
+    /// role chain.
+    #[serde(default)]
+    pub transitive_tag_keys: Vec<String>,
+
+    /// The requested session duration in seconds, by default one hour.
+    #[serde(default)]
+    pub duration_seconds: Option<u32>,
+
+    /// Session policies limiting the permissions of the new session.
+    #[serde(default)]
+    pub session_policies: Vec<SessionPolicy>,
+}
+
+impl AssumeRoleRequest {
+    /// Make a request to assume a role, given by its ARN or name, with default
+    /// parameters.
+    pub fn new(role: &str) -> AssumeRoleRequest {
+        AssumeRoleRequest {
+            role: role.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A principal making requests, along with the session tags it carries.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct Session {
+    /// The principal: for an assumed role, the session ARN.
+    pub principal: Principal,
+
+    /// Session tags, including those inherited as transitive tags.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+
+    /// Keys of the session tags that are passed on when this session assumes
+    /// another role.
+    #[serde(default)]
+    pub transitive_tag_keys: Vec<String>,
+
+    /// Session policies passed when the session was created.
+    #[serde(default)]
+    pub session_policies: Vec<SessionPolicy>,
+}
+
+impl Session {
+    /// Start from a principal that has no session tags, such as a user.
+    pub fn new(principal: Principal) -> Session {
+        Session {
+            principal,
+            tags: Vec::new(),
+            transitive_tag_keys: Vec::new(),
+            session_policies: Vec::new(),
+        }
+    }
+
+    /// Make a request from this session.
+    ///
+    /// The session tags are included in the context as `aws:PrincipalTag/...`,
+    /// where they take precedence over any tags on the role, and the session policies
+    /// are passed with the request.
+    pub fn request(&self, action: &str, resource: &str) -> Request {
+        let mut context = Context::new();
+        for tag in &self.tags {
+            context.set_principal_tag(&tag.key, &tag.value);
+        }
+        Request {
+            context,
+            session_policies: self.session_policies.clone(),
+            ..Request::new(self.principal.clone(), action, resource)
+        }
+    }
+
+    /// The tags that this session passes on to a session it creates.
+    fn transitive_tags(&self) -> impl Iterator<Item = &Tag> {
+        self.tags.iter().filter(|tag| {
+            self.transitive_tag_keys
+                .iter()
+                .any(|key| key.eq_ignore_ascii_case(&tag.key))
+        })
+    }
+}
+
+/// The outcome of one `sts:AssumeRole` call in a chain.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct AssumeRoleHop {
+    /// The ARN of the role to be assumed.
+    pub role_arn: String,
+
+    /// The principal trying to assume the role.
+    pub caller: Principal,
+
+    /// True if the caller's identity policies, within its permissions boundary, allow
+    /// `sts:AssumeRole` on the role.
+    pub identity_allows: bool,
+
+    /// True if the role's trust policy allows the caller to assume it.
+    pub trust_allows: bool,
+
+    /// If session tags are passed, whether `sts:TagSession` is allowed by both the
+    /// identity and trust policies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag_session_allows: Option<bool>,
+
+    /// Why the role could not be assumed, or None if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denied_because: Option<String>,
+}
+
+impl AssumeRoleHop {
+    pub fn is_allowed(&self) -> bool {
+        self.denied_because.is_none()
+    }
+}
+
+/// The outcome of a chain of `sts:AssumeRole` calls.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct AssumeRoleChain {
+    /// Each hop that was attempted, stopping after the first that is denied.
+    pub hops: Vec<AssumeRoleHop>,
+
+    /// The final session, if every hop was allowed.
+    pub session: Option<Session>,
+}
+
+impl AssumeRoleChain {
+    pub fn is_allowed(&self) -> bool {
+        self.session.is_some()
+    }
+}
+
+impl Scenario {
+    /// Simulate a chain of `sts:AssumeRole` calls, starting from the given session and
+    /// with each new session assuming the next role.
+    ///
+    /// Each hop is allowed only if:
+    ///
+    /// * The caller's identity policies allow `sts:AssumeRole` on the role. Within
+    ///   one account this isn't needed if the trust policy names the caller's exact
+    ///   ARN, as for other resource policies in [Scenario::eval]; but a trust policy
+    ///   that names only the account leaves it to the account's identity policies.
+    /// * The role's trust policy allows the caller.
+    /// * [Scenario::eval] allows the request, so that explicit denies, service
+    ///   control policies, permissions boundaries and session policies apply, and a
+    ///   role in another account must be allowed by both the caller's identity
+    ///   policies and the trust policy.
+    /// * If any session tags are passed, including transitive tags from the caller,
+    ///   `sts:TagSession` is allowed in the same way.
+    /// * The session duration is within the role's maximum, and within one hour
+    ///   if the caller is itself a role session.
+    ///
+    /// A role given by name is looked up in the account of the session assuming it,
+    /// so a chain can cross into another account by giving a role's ARN.
+    ///
+    /// Session policies apply only to the session created by the hop they're passed
+    /// to, not to later sessions in the chain.
+    ///
+    /// It's an error if a role or managed session policy is not in the scenario, or
+    /// the starting principal's account can't be determined.
+    pub fn assume_role_chain(
+        &self,
+        start: Session,
+        steps: &[AssumeRoleRequest],
+    ) -> Result<AssumeRoleChain> {
+        let mut session = start;
+        let mut hops = Vec::new();
+        for step in steps {
+            let caller_arn = match &session.principal {
+                Principal::ARN(arn) => Arn::from_str(arn)?,
+                Principal::Service(service) => {
+                    return Err(Error::AssumeRoleFromService {
+                        service: service.clone(),
+                    })
+                }
+            };
+            let (hop, new_session) = self.assume_role(&session, &caller_arn, step)?;
+            info!(
+                role_arn = hop.role_arn,
+                allowed = hop.is_allowed(),
+                "AssumeRole"
+            );
+            hops.push(hop);
+            match new_session {
+                Some(new_session) => session = new_session,
+                None => {
+                    return Ok(AssumeRoleChain {
+                        hops,
+                        session: None,
+                    })
+                }
+            }
+        }
+        Ok(AssumeRoleChain {
+            hops,
+            session: Some(session),
+        })
+    }
+
+    /// Simulate one `sts:AssumeRole` call, returning the new session if it's allowed.
+    fn assume_role(
+        &self,
+        caller: &Session,
+        caller_arn: &Arn,
+        step: &AssumeRoleRequest,
+    ) -> Result<(AssumeRoleHop, Option<Session>)> {
+        // The ARN of the role, or for a role given by name, of the caller, gives the
+        // role's account.
+        let (role, account) = if step.role.starts_with("arn:") {
+            let arn = Arn::from_str(&step.role)?;
+            (self.find_role_by_arn(&arn), arn)
+        } else {
+            (
+                self.find_role_in_account(&caller_arn.account_id, &step.role),
+                caller_arn.clone(),
+            )
+        };
+        let Some(role) = role else {
+            return Err(Error::undefined(None, ReferenceKind::Role, &step.role));
+        };
+        let role_arn = format!(
+            "arn:{}:iam::{}:{}",
+            account.partition,
+            account.account_id,
+            role.arn_resource()
+        );
+        for session_policy in &step.session_policies {
+            self.resolve_session_policy(session_policy)?;
+        }
+        let session_name = step
+            .role_session_name
+            .as_deref()
+            .unwrap_or(DEFAULT_SESSION_NAME);
+
+        // The new session's tags are those inherited from the caller, and those passed
+        // in this call, which may not override inherited tags.
+        let mut tags = caller.transitive_tags().cloned().collect::<Vec<Tag>>();
+        let mut transitive_tag_keys = tags.iter().map(|tag| tag.key.clone()).collect::<Vec<_>>();
+        let mut denied_because = None;
+        for tag in &step.tags {
+            if tags.iter().any(|t| t.key.eq_ignore_ascii_case(&tag.key)) {
+                denied_because = Some(format!(
+                    "Session tag {:?} can't override a transitive tag from the caller",
+                    tag.key
+                ));
+            }
+            tags.push(tag.clone());
+        }
+        for key in &step.transitive_tag_keys {
+            if !step
+                .tags
+                .iter()
+                .any(|tag| tag.key.eq_ignore_ascii_case(key))
+            {
+                denied_because = Some(format!(
+                    "Transitive tag key {key:?} is not one of the session tags"
+                ));
+            }
+            transitive_tag_keys.push(key.clone());
+        }
+
+        let mut request = caller.request(ASSUME_ROLE, &role_arn);
+        request
+            .context
+            .insert(context::ROLE_SESSION_NAME, [session_name]);
+        for tag in &tags {
+            request.context.set_request_tag(&tag.key, &tag.value);
+        }
+        if !tags.is_empty() {
+            request
+                .context
+                .set_tag_keys(tags.iter().map(|tag| tag.key.as_str()));
+        }
+        if !step.transitive_tag_keys.is_empty() {
+            request
+                .context
+                .insert(context::TRANSITIVE_TAG_KEYS, &step.transitive_tag_keys);
+        }
+
+        let check = self.check_assume_role(role, &request)?;
+        let tag_session_allows = if tags.is_empty() {
+            None
+        } else {
+            let request = Request {
+                action: TAG_SESSION.to_owned(),
+                ..request.clone()
+            };
+            Some(self.check_assume_role(role, &request)?.is_allowed())
+        };
+        debug!(?request, ?check, ?tag_session_allows);
+
+        let duration = step.duration_seconds.unwrap_or(ONE_HOUR);
+        let role_chaining = caller.principal.assumed_role().is_some();
+        if denied_because.is_some() {
+            // Already denied because of the session tags.
+        } else if !check.identity_allows && !check.trust_names_caller {
+            denied_because = Some(format!(
+                "The caller's identity policies don't allow {ASSUME_ROLE} on the role"
+            ));
+        } else if !check.trust_allows {
+            denied_because = Some("The role's trust policy doesn't allow the caller".to_owned());
+        } else if !check.decision.is_allow() {
+            denied_because = Some(format!("{ASSUME_ROLE} is denied: {}", check.decision));
+        } else if tag_session_allows == Some(false) {
+            denied_because = Some(format!(
+                "Session tags were passed but {TAG_SESSION} is not allowed"
+            ));
+        } else if duration < MIN_DURATION || duration > role.max_session_duration {
+            denied_because = Some(format!(
+                "Session duration {duration} is not between {MIN_DURATION} and the role's maximum of {}",
+                role.max_session_duration
+            ));
+        } else if role_chaining && duration > ONE_HOUR {
+            denied_because = Some(format!(
+                "Session duration {duration} is more than the role chaining limit of {ONE_HOUR}"
+            ));
+        }
+
+        let new_session = denied_because.is_none().then(|| Session {
+            principal: Principal::ARN(
+                AssumedRole {
+                    partition: account.partition.clone(),
+                    account_id: account.account_id.clone(),
+                    role_name: role.role_name.clone(),
+                    session_name: session_name.to_owned(),
+                }
+                .arn(),
+            ),
+            tags,
+            transitive_tag_keys,
+            session_policies: step.session_policies.clone(),
+        });
+        let hop = AssumeRoleHop {
+            role_arn,
+            caller: caller.principal.clone(),
+            identity_allows: check.identity_allows,
+            trust_allows: check.trust_allows,
+            tag_session_allows,
+            denied_because,
+        };
+        Ok((hop, new_session))
+    }
+
+    /// Evaluate a request to assume or tag a session of a role.
+    fn check_assume_role(&self, role: &Role, request: &Request) -> Result<AssumeRoleCheck> {
+        let decision = self.eval(request)?;
+        let filled = Request {
+            context: self.request_context(request),
+            ..request.clone()
+        };
+        let identity_allows = self
+            .identity_policies(&request.principal)?
+            .iter()
+            .any(|(_name, policy)| policy.allows(&filled))
+            && self
+                .permissions_boundary(&request.principal)?
+                .is_none_or(|(_name, policy)| policy.allows(&filled));
+        let trust_policy = &role.assume_role_policy_document.0;
+        Ok(AssumeRoleCheck {
+            identity_allows,
+            trust_allows: trust_policy.allows(&filled),
+            trust_names_caller: !self.is_cross_account(&filled)
+                && trust_policy.allows_named_principal(&filled),
+            decision,
+        })
+    }
+}
+
+/// Whether the policies involved allow a request to assume or tag a session of a
+/// role.
+#[derive(Debug)]
+struct AssumeRoleCheck {
+    /// True if the caller's identity policies, within its permissions boundary,
+    /// allow the request.
+    identity_allows: bool,
+    /// True if the role's trust policy allows the request.
+    trust_allows: bool,
+    /// True if the role is in the caller's account and its trust policy allows the
+    /// request by naming the caller's exact ARN, so that no identity policy is needed.
+    trust_names_caller: bool,
+    /// The decision from [Scenario::eval].
+    decision: Decision,
+}
+
+impl AssumeRoleCheck {
+    fn is_allowed(&self) -> bool {
+        (self.identity_allows || self.trust_names_caller)
+            && self.trust_allows
+            && self.decision.is_allow()
+    }
+}
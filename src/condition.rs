@@ -0,0 +1,673 @@
+// Copyright 2023 Martin Pool
+
+//! The `Condition` element of policy statements, and the condition operators
+//! that compare it to the request context.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_elements_condition_operators.html>.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::arn::arn_pattern_matches;
+use crate::context::Context;
+use crate::error::Error;
+use crate::glob::Pattern;
+use crate::json::ScalarOrList;
+use crate::trace::{Element, ElementTrace};
+use crate::variable::{expand_all, expands_variables};
+
+/// The `Condition` element of a statement.
+///
+/// This maps from a condition operator, such as `StringEquals`, to a map from
+/// condition keys to the values they are compared against. The condition is
+/// satisfied only if every operator is satisfied for every key.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Condition(pub BTreeMap<ConditionOperator, BTreeMap<String, Vec<String>>>);
+
+impl Condition {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// True if every operator in this condition is satisfied for every key,
+    /// given the request context, in a policy with this `Version`.
+    ///
+    /// Policy variables in the values are expanded from the context only if the
+    /// version supports them, as for [Statement::expands_variables](crate::policy::Statement::expands_variables).
+    pub fn matches(&self, context: &Context, version: Option<&str>) -> bool {
+        self.matches_with(context, expands_variables(version))
+    }
+
+    /// True if every operator in this condition is satisfied for every key.
+    ///
+    /// If `expand_variables` is true, policy variables in the values are expanded
+    /// from the context, and the condition is not satisfied if any of them can't be
+    /// resolved. Otherwise the values are taken literally, as in policies written
+    /// before variables were introduced.
+    pub fn matches_with(&self, context: &Context, expand_variables: bool) -> bool {
+        self.0.iter().all(|(operator, keys)| {
+            keys.iter().all(|(key, values)| {
+                let Some(patterns) = expand_all(values, context, expand_variables) else {
+                    return false;
+                };
+                operator.matches(context.get(key), &patterns)
+            })
+        })
+    }
+
+    /// Explain whether each operator is satisfied for each key, as for [Condition::matches_with].
+    pub fn trace(&self, context: &Context, expand_variables: bool) -> Vec<ElementTrace> {
+        let mut traces = Vec::new();
+        for (operator, keys) in &self.0 {
+            for (key, values) in keys {
+                let Some(patterns) = expand_all(values, context, expand_variables) else {
+                    traces.push(ElementTrace::new(
+                        Element::Condition,
+                        false,
+                        format!("{operator} {key}: {values:?} has an unresolvable policy variable"),
+                    ));
+                    continue;
+                };
+                let context_values = context.get(key);
+                let matched = operator.matches(context_values, &patterns);
+                let policy_values = patterns
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect::<Vec<_>>();
+                let satisfies = if matched {
+                    "satisfies"
+                } else {
+                    "does not satisfy"
+                };
+                let request_values = match context_values {
+                    None => "key is not present in the request, which".to_owned(),
+                    Some([value]) => format!("request value {value:?}"),
+                    Some(values) => format!("request values {values:?}"),
+                };
+                let explanation =
+                    format!("{operator} {key}: {request_values} {satisfies} {policy_values:?}");
+                traces.push(ElementTrace::new(Element::Condition, matched, explanation));
+            }
+        }
+        traces
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map = BTreeMap::<ConditionOperator, BTreeMap<String, ScalarOrList>>::deserialize(
+            deserializer,
+        )?;
+        Ok(Condition(
+            map.into_iter()
+                .map(|(operator, keys)| {
+                    (
+                        operator,
+                        keys.into_iter()
+                            .map(|(key, ScalarOrList(values))| (key, values))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// In JSON each value may be a single string, boolean or number, or a list of them.
+impl JsonSchema for Condition {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Condition".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        BTreeMap::<String, BTreeMap<String, ScalarOrList>>::json_schema(gen)
+    }
+}
+
+/// A condition operator as written in a policy, such as `StringEquals` or
+/// `ForAllValues:StringLikeIfExists`: a base [Operator], optionally qualified
+/// by a set operator and an `IfExists` suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConditionOperator {
+    /// How to compare a key with multiple values in the request, if qualified by
+    /// `ForAllValues:` or `ForAnyValue:`.
+    pub set_operator: Option<SetOperator>,
+    /// The base operator.
+    pub operator: Operator,
+    /// If true, the operator has the `IfExists` suffix and is satisfied when the key is
+    /// not present in the request.
+    pub if_exists: bool,
+}
+
+/// A qualifier for comparing multi-valued condition keys.
+///
+/// See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-single-vs-multi-valued-context-keys.html>.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SetOperator {
+    /// Every value of the key in the request must match.
+    ForAllValues,
+    /// At least one value of the key in the request must match.
+    ForAnyValue,
+}
+
+impl ConditionOperator {
+    /// Evaluate this operator for one condition key.
+    ///
+    /// `context_values` are the values of the key in the request context, or None
+    /// if the key is not present. `policy_values` are the values given for the key
+    /// in the policy.
+    ///
+    /// With `IfExists`, a missing key satisfies the condition.
+    ///
+    /// With `ForAllValues`, every value of the key in the request must match one of
+    /// the policy values, or, for a negated operator, match none of them. Note that this
+    /// means that `ForAllValues` is satisfied if the key is missing or has no values,
+    /// though not if its only value is an empty string.
+    ///
+    /// With `ForAnyValue`, at least one value of the key in the request must match
+    /// (or for a negated operator, not match). A missing key or an empty set of
+    /// values does not satisfy the condition.
+    ///
+    /// Without a set operator, see [Operator::matches].
+    pub fn matches(&self, context_values: Option<&[String]>, policy_values: &[Pattern]) -> bool {
+        if self.if_exists && context_values.is_none() {
+            return true;
+        }
+        let Some(set_operator) = self.set_operator else {
+            return self.operator.matches(context_values, policy_values);
+        };
+        let context_values = context_values.unwrap_or_default();
+        let value_matches = |context_value: &String| {
+            self.operator
+                .one_value_matches(context_value, policy_values)
+        };
+        match set_operator {
+            SetOperator::ForAllValues => context_values.iter().all(value_matches),
+            SetOperator::ForAnyValue => context_values.iter().any(value_matches),
+        }
+    }
+}
+
+impl fmt::Display for ConditionOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.set_operator {
+            Some(SetOperator::ForAllValues) => f.write_str("ForAllValues:")?,
+            Some(SetOperator::ForAnyValue) => f.write_str("ForAnyValue:")?,
+            None => (),
+        }
+        f.write_str(self.operator.name())?;
+        if self.if_exists {
+            f.write_str("IfExists")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ConditionOperator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidConditionOperator {
+            operator: s.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let (set_operator, rest) = if let Some(rest) = s.strip_prefix("ForAllValues:") {
+            (Some(SetOperator::ForAllValues), rest)
+        } else if let Some(rest) = s.strip_prefix("ForAnyValue:") {
+            (Some(SetOperator::ForAnyValue), rest)
+        } else {
+            (None, s)
+        };
+        let (base, if_exists) = match rest.strip_suffix("IfExists") {
+            Some(base) => (base, true),
+            None => (rest, false),
+        };
+        let operator = base
+            .parse::<Operator>()
+            .map_err(|_| invalid("unknown condition operator"))?;
+        if operator == Operator::Null && (if_exists || set_operator.is_some()) {
+            return Err(invalid("the Null condition operator can't be qualified"));
+        }
+        Ok(ConditionOperator {
+            set_operator,
+            operator,
+            if_exists,
+        })
+    }
+}
+
+impl From<Operator> for ConditionOperator {
+    fn from(operator: Operator) -> Self {
+        ConditionOperator {
+            set_operator: None,
+            operator,
+            if_exists: false,
+        }
+    }
+}
+
+impl Serialize for ConditionOperator {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConditionOperator {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A base condition operator, such as `StringEquals`.
+///
+/// This is a fixed set: an operator not listed here is an error when the policy is
+/// loaded. To support another one, add it here, to [Operator::ALL] and
+/// [Operator::name], and say how it compares values in [Operator::matches].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operator {
+    StringEquals,
+    StringNotEquals,
+    StringEqualsIgnoreCase,
+    StringNotEqualsIgnoreCase,
+    StringLike,
+    StringNotLike,
+    NumericEquals,
+    NumericNotEquals,
+    NumericLessThan,
+    NumericLessThanEquals,
+    NumericGreaterThan,
+    NumericGreaterThanEquals,
+    DateEquals,
+    DateNotEquals,
+    DateLessThan,
+    DateLessThanEquals,
+    DateGreaterThan,
+    DateGreaterThanEquals,
+    Bool,
+    BinaryEquals,
+    IpAddress,
+    NotIpAddress,
+    ArnEquals,
+    ArnLike,
+    ArnNotEquals,
+    ArnNotLike,
+    Null,
+}
+
+impl Operator {
+    /// All the operators.
+    pub const ALL: &'static [Operator] = &[
+        Operator::StringEquals,
+        Operator::StringNotEquals,
+        Operator::StringEqualsIgnoreCase,
+        Operator::StringNotEqualsIgnoreCase,
+        Operator::StringLike,
+        Operator::StringNotLike,
+        Operator::NumericEquals,
+        Operator::NumericNotEquals,
+        Operator::NumericLessThan,
+        Operator::NumericLessThanEquals,
+        Operator::NumericGreaterThan,
+        Operator::NumericGreaterThanEquals,
+        Operator::DateEquals,
+        Operator::DateNotEquals,
+        Operator::DateLessThan,
+        Operator::DateLessThanEquals,
+        Operator::DateGreaterThan,
+        Operator::DateGreaterThanEquals,
+        Operator::Bool,
+        Operator::BinaryEquals,
+        Operator::IpAddress,
+        Operator::NotIpAddress,
+        Operator::ArnEquals,
+        Operator::ArnLike,
+        Operator::ArnNotEquals,
+        Operator::ArnNotLike,
+        Operator::Null,
+    ];
+
+    /// The name of the operator as it appears in policies.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operator::StringEquals => "StringEquals",
+            Operator::StringNotEquals => "StringNotEquals",
+            Operator::StringEqualsIgnoreCase => "StringEqualsIgnoreCase",
+            Operator::StringNotEqualsIgnoreCase => "StringNotEqualsIgnoreCase",
+            Operator::StringLike => "StringLike",
+            Operator::StringNotLike => "StringNotLike",
+            Operator::NumericEquals => "NumericEquals",
+            Operator::NumericNotEquals => "NumericNotEquals",
+            Operator::NumericLessThan => "NumericLessThan",
+            Operator::NumericLessThanEquals => "NumericLessThanEquals",
+            Operator::NumericGreaterThan => "NumericGreaterThan",
+            Operator::NumericGreaterThanEquals => "NumericGreaterThanEquals",
+            Operator::DateEquals => "DateEquals",
+            Operator::DateNotEquals => "DateNotEquals",
+            Operator::DateLessThan => "DateLessThan",
+            Operator::DateLessThanEquals => "DateLessThanEquals",
+            Operator::DateGreaterThan => "DateGreaterThan",
+            Operator::DateGreaterThanEquals => "DateGreaterThanEquals",
+            Operator::Bool => "Bool",
+            Operator::BinaryEquals => "BinaryEquals",
+            Operator::IpAddress => "IpAddress",
+            Operator::NotIpAddress => "NotIpAddress",
+            Operator::ArnEquals => "ArnEquals",
+            Operator::ArnLike => "ArnLike",
+            Operator::ArnNotEquals => "ArnNotEquals",
+            Operator::ArnNotLike => "ArnNotLike",
+            Operator::Null => "Null",
+        }
+    }
+
+    /// For a negated operator such as `StringNotEquals`, return the corresponding
+    /// positive operator, `StringEquals`. Other operators are returned unchanged.
+    pub fn positive(self) -> Operator {
+        match self {
+            Operator::StringNotEquals => Operator::StringEquals,
+            Operator::StringNotEqualsIgnoreCase => Operator::StringEqualsIgnoreCase,
+            Operator::StringNotLike => Operator::StringLike,
+            Operator::NumericNotEquals => Operator::NumericEquals,
+            Operator::DateNotEquals => Operator::DateEquals,
+            Operator::NotIpAddress => Operator::IpAddress,
+            Operator::ArnNotEquals => Operator::ArnEquals,
+            Operator::ArnNotLike => Operator::ArnLike,
+            other => other,
+        }
+    }
+
+    /// True for operators such as `StringNotEquals` that are satisfied when
+    /// the value does *not* match.
+    pub fn is_negated(self) -> bool {
+        self.positive() != self
+    }
+
+    /// Evaluate this operator, without any qualifiers, for one condition key.
+    ///
+    /// `context_values` are the values of the key in the request context, or None
+    /// if the key is not present. `policy_values` are the values given for the key
+    /// in the policy, any one of which may match.
+    ///
+    /// If the key is not present in the request, positive operators are not satisfied
+    /// and negated operators are satisfied. If the key has several values in the
+    /// request, a positive operator matches if any of them match, and a negated
+    /// operator matches if none of them match.
+    pub fn matches(self, context_values: Option<&[String]>, policy_values: &[Pattern]) -> bool {
+        if self == Operator::Null {
+            let absent = context_values.is_none_or(<[String]>::is_empty);
+            return policy_values
+                .iter()
+                .any(|value| value.to_string().eq_ignore_ascii_case("true") == absent);
+        }
+        match context_values {
+            None => self.is_negated(),
+            Some(context_values) => {
+                let positive = self.positive();
+                let any_match = context_values
+                    .iter()
+                    .any(|context_value| positive.one_value_matches(context_value, policy_values));
+                any_match != self.is_negated()
+            }
+        }
+    }
+
+    /// Compare one value from the request context to all the values for the key in
+    /// the policy.
+    ///
+    /// A positive operator matches if any policy value matches; a negated operator
+    /// matches if no policy value matches.
+    fn one_value_matches(self, context_value: &str, policy_values: &[Pattern]) -> bool {
+        let positive = self.positive();
+        policy_values
+            .iter()
+            .any(|policy_value| positive.value_matches(context_value, policy_value))
+            != self.is_negated()
+    }
+
+    /// Compare one value from the request context to one value from the policy,
+    /// using the positive form of this operator.
+    ///
+    /// Wildcards in the policy value are only meaningful to the `Like` operators;
+    /// other operators compare against the value as written.
+    fn value_matches(self, context_value: &str, policy_pattern: &Pattern) -> bool {
+        match self {
+            Operator::StringLike => return policy_pattern.matches(context_value),
+            Operator::ArnEquals | Operator::ArnLike => {
+                return arn_pattern_matches(policy_pattern, context_value)
+            }
+            _ => (),
+        }
+        let policy_value = &policy_pattern.to_string();
+        match self {
+            Operator::StringEquals => context_value == policy_value,
+            Operator::BinaryEquals => {
+                match (decode_binary(context_value), decode_binary(policy_value)) {
+                    (Some(c), Some(p)) => c == p,
+                    _ => false,
+                }
+            }
+            Operator::StringEqualsIgnoreCase => {
+                context_value.to_lowercase() == policy_value.to_lowercase()
+            }
+            Operator::NumericEquals
+            | Operator::NumericLessThan
+            | Operator::NumericLessThanEquals
+            | Operator::NumericGreaterThan
+            | Operator::NumericGreaterThanEquals => {
+                match (parse_number(context_value), parse_number(policy_value)) {
+                    (Some(c), Some(p)) => self.compare(c.partial_cmp(&p)),
+                    _ => false,
+                }
+            }
+            Operator::DateEquals
+            | Operator::DateLessThan
+            | Operator::DateLessThanEquals
+            | Operator::DateGreaterThan
+            | Operator::DateGreaterThanEquals => {
+                match (parse_date(context_value), parse_date(policy_value)) {
+                    (Some(c), Some(p)) => self.compare(Some(c.cmp(&p))),
+                    _ => false,
+                }
+            }
+            Operator::Bool => context_value.eq_ignore_ascii_case(policy_value),
+            Operator::IpAddress => ip_in_cidr(context_value, policy_value),
+            _ => false,
+        }
+    }
+
+    /// For numeric and date operators, interpret the ordering of the context
+    /// value relative to the policy value.
+    fn compare(self, ordering: Option<std::cmp::Ordering>) -> bool {
+        use std::cmp::Ordering::*;
+        match (self, ordering) {
+            (_, None) => false,
+            (Operator::NumericEquals | Operator::DateEquals, Some(o)) => o == Equal,
+            (Operator::NumericLessThan | Operator::DateLessThan, Some(o)) => o == Less,
+            (Operator::NumericLessThanEquals | Operator::DateLessThanEquals, Some(o)) => {
+                o != Greater
+            }
+            (Operator::NumericGreaterThan | Operator::DateGreaterThan, Some(o)) => o == Greater,
+            (Operator::NumericGreaterThanEquals | Operator::DateGreaterThanEquals, Some(o)) => {
+                o != Less
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Operator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Operator::ALL
+            .iter()
+            .find(|op| op.name() == s)
+            .copied()
+            .ok_or_else(|| Error::InvalidConditionOperator {
+                operator: s.to_owned(),
+                reason: "unknown condition operator".to_owned(),
+            })
+    }
+}
+
+/// Test whether an IP address is within a CIDR block such as "203.0.113.0/24",
+/// or equal to a single address.
+///
+/// IPv4 addresses never match IPv6 blocks or vice versa.
+fn ip_in_cidr(ip: &str, cidr: &str) -> bool {
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return false;
+    };
+    let (network, prefix_len) = match cidr.split_once('/') {
+        Some((network, prefix_len)) => match prefix_len.parse::<u32>() {
+            Ok(prefix_len) => (network, Some(prefix_len)),
+            Err(_) => return false,
+        },
+        None => (cidr, None),
+    };
+    let Ok(network) = network.parse::<IpAddr>() else {
+        return false;
+    };
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    let prefix_len = prefix_len.unwrap_or(bits);
+    if prefix_len > bits {
+        false
+    } else if prefix_len == 0 {
+        true
+    } else {
+        let shift = bits - prefix_len;
+        (ip >> shift) == (network >> shift)
+    }
+}
+
+/// Parse a number as used in `Numeric` conditions.
+///
+/// Values that Rust parses as infinite or not-a-number, such as "inf" or "NaN",
+/// are not valid numbers in a policy or request.
+pub(crate) fn parse_number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Decode a base64 value as used in `BinaryEquals` conditions, where both the
+/// policy and the request give binary values in base64.
+fn decode_binary(s: &str) -> Option<Vec<u8>> {
+    BASE64_STANDARD.decode(s).ok()
+}
+
+/// Parse a date as used in `Date` conditions, returning milliseconds since the
+/// Unix epoch.
+///
+/// Accepts ISO 8601 dates such as "2023-06-01", date-times such as
+/// "2023-06-01T12:00:00Z" or "2023-06-01T12:00:00.500+10:00", or an integer
+/// number of seconds since the epoch.
+pub(crate) fn parse_date(s: &str) -> Option<i64> {
+    if let Ok(epoch_seconds) = s.parse::<i64>() {
+        return epoch_seconds.checked_mul(1000);
+    }
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let mut date_parts = date.split('-');
+    let year = parse_digits(date_parts.next()?, 4)?;
+    let month = parse_digits(date_parts.next()?, 2)?;
+    let day = parse_digits(date_parts.next()?, 2)?;
+    if date_parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+    if let Some(time) = time {
+        let (clock, offset_minutes) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+            (clock, 0)
+        } else if let Some(i) = time.rfind(['+', '-']) {
+            let (clock, offset) = time.split_at(i);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let offset = offset[1..].replace(':', "");
+            let hours = parse_digits(offset.get(..2)?, 2)?;
+            let minutes = parse_digits(offset.get(2..)?, 2)?;
+            (clock, sign * (hours * 60 + minutes))
+        } else {
+            (time, 0)
+        };
+        let (clock, fraction) = match clock.split_once('.') {
+            Some((clock, fraction)) => (clock, Some(fraction)),
+            None => (clock, None),
+        };
+        let mut clock_parts = clock.split(':');
+        let hours = parse_digits(clock_parts.next()?, 2)?;
+        let minutes = parse_digits(clock_parts.next()?, 2)?;
+        let seconds = match clock_parts.next() {
+            Some(seconds) => parse_digits(seconds, 2)?,
+            None => 0,
+        };
+        if clock_parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+            return None;
+        }
+        let fraction_millis = match fraction {
+            Some(fraction)
+                if !fraction.is_empty() && fraction.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                format!("{fraction:0<3}")[..3].parse::<i64>().ok()?
+            }
+            Some(_) => return None,
+            None => 0,
+        };
+        millis += ((hours * 60 + minutes - offset_minutes) * 60 + seconds) * 1000 + fraction_millis;
+    }
+    Some(millis)
+}
+
+/// Parse a string of exactly `len` ASCII digits.
+fn parse_digits(s: &str, len: usize) -> Option<i64> {
+    if s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// The number of days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+///
+/// From <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
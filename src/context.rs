@@ -0,0 +1,392 @@
+// Copyright 2023 Martin Pool
+
+//! The request context: condition keys and their values for one request.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html#policy-eval-reqcontext>
+//! and <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_condition-keys.html>.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::ser::SerializeMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+use crate::condition::{parse_date, parse_number};
+use crate::error::{Error, Result};
+use crate::json::ScalarOrList;
+
+/// The source IP address of the request.
+pub const SOURCE_IP: &str = "aws:SourceIp";
+/// The date and time of the request.
+pub const CURRENT_TIME: &str = "aws:CurrentTime";
+/// The date and time of the request, in seconds since the Unix epoch.
+pub const EPOCH_TIME: &str = "aws:EpochTime";
+/// Whether the request was sent using TLS.
+pub const SECURE_TRANSPORT: &str = "aws:SecureTransport";
+/// Whether the principal authenticated with MFA.
+pub const MULTI_FACTOR_AUTH_PRESENT: &str = "aws:MultiFactorAuthPresent";
+/// Seconds since the principal authenticated with MFA.
+pub const MULTI_FACTOR_AUTH_AGE: &str = "aws:MultiFactorAuthAge";
+/// The region the request is sent to.
+pub const REQUESTED_REGION: &str = "aws:RequestedRegion";
+/// The keys of the tags passed in the request.
+pub const TAG_KEYS: &str = "aws:TagKeys";
+/// The friendly name of the calling IAM user.
+pub const USERNAME: &str = "aws:username";
+/// The unique id of the caller.
+pub const USERID: &str = "aws:userid";
+/// The account that the calling principal belongs to.
+pub const PRINCIPAL_ACCOUNT: &str = "aws:PrincipalAccount";
+/// The ARN of the calling principal.
+pub const PRINCIPAL_ARN: &str = "aws:PrincipalArn";
+/// The type of the calling principal, such as "User" or "AssumedRole".
+pub const PRINCIPAL_TYPE: &str = "aws:PrincipalType";
+/// The service principal making a request on its own behalf, e.g. `cloudtrail.amazonaws.com`.
+pub const PRINCIPAL_SERVICE_NAME: &str = "aws:PrincipalServiceName";
+/// The session name passed to `sts:AssumeRole`.
+pub const ROLE_SESSION_NAME: &str = "sts:RoleSessionName";
+/// The keys of session tags passed to `sts:AssumeRole` as transitive.
+pub const TRANSITIVE_TAG_KEYS: &str = "sts:TransitiveTagKeys";
+/// The account that owns the resource of the request.
+pub const RESOURCE_ACCOUNT: &str = "aws:ResourceAccount";
+/// Prefix for the tags attached to the principal, e.g. `aws:PrincipalTag/team`.
+pub const PRINCIPAL_TAG_PREFIX: &str = "aws:PrincipalTag/";
+/// Prefix for the tags passed in the request, e.g. `aws:RequestTag/team`.
+pub const REQUEST_TAG_PREFIX: &str = "aws:RequestTag/";
+/// Prefix for the tags attached to the resource, e.g. `aws:ResourceTag/team`.
+pub const RESOURCE_TAG_PREFIX: &str = "aws:ResourceTag/";
+
+/// The type of values of a condition key, which is checked when the context is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    String,
+    Bool,
+    Date,
+    Numeric,
+    IpAddress,
+}
+
+/// The types of some well-known global condition keys: the key name, the type, and
+/// whether the key may have multiple values.
+///
+/// Tags are also typed by their prefixes. Any other keys are accepted as
+/// multi-valued strings.
+const KNOWN_KEYS: &[(&str, KeyType, bool)] = &[
+    (SOURCE_IP, KeyType::IpAddress, false),
+    (CURRENT_TIME, KeyType::Date, false),
+    (EPOCH_TIME, KeyType::Date, false),
+    (SECURE_TRANSPORT, KeyType::Bool, false),
+    (MULTI_FACTOR_AUTH_PRESENT, KeyType::Bool, false),
+    (MULTI_FACTOR_AUTH_AGE, KeyType::Numeric, false),
+    (REQUESTED_REGION, KeyType::String, false),
+    (TAG_KEYS, KeyType::String, true),
+    (USERNAME, KeyType::String, false),
+    (USERID, KeyType::String, false),
+    (PRINCIPAL_ACCOUNT, KeyType::String, false),
+    (PRINCIPAL_ARN, KeyType::String, false),
+    (PRINCIPAL_TYPE, KeyType::String, false),
+    (PRINCIPAL_SERVICE_NAME, KeyType::String, false),
+    (ROLE_SESSION_NAME, KeyType::String, false),
+    (TRANSITIVE_TAG_KEYS, KeyType::String, true),
+    (RESOURCE_ACCOUNT, KeyType::String, false),
+];
+
+const TAG_PREFIXES: &[&str] = &[
+    PRINCIPAL_TAG_PREFIX,
+    REQUEST_TAG_PREFIX,
+    RESOURCE_TAG_PREFIX,
+];
+
+/// Return the type of a key, and whether it may have multiple values.
+pub fn key_type(key: &str) -> (KeyType, bool) {
+    if let Some((_, key_type, multi_valued)) = KNOWN_KEYS
+        .iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(key))
+    {
+        (*key_type, *multi_valued)
+    } else if TAG_PREFIXES
+        .iter()
+        .any(|prefix| starts_with_ignore_case(key, prefix))
+    {
+        (KeyType::String, false)
+    } else {
+        (KeyType::String, true)
+    }
+}
+
+/// Check that the values are acceptable for a key, according to [key_type].
+pub fn validate(key: &str, values: &[String]) -> Result<()> {
+    let (key_type, multi_valued) = key_type(key);
+    if !multi_valued && values.len() != 1 {
+        return Err(Error::InvalidContextValue {
+            key: key.to_owned(),
+            reason: "must have exactly one value".to_owned(),
+        });
+    }
+    for value in values {
+        let valid = match key_type {
+            KeyType::String => true,
+            KeyType::Bool => {
+                value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+            }
+            KeyType::Date => parse_date(value).is_some(),
+            KeyType::Numeric => parse_number(value).is_some(),
+            KeyType::IpAddress => value.parse::<IpAddr>().is_ok(),
+        };
+        if !valid {
+            return Err(Error::InvalidContextValue {
+                key: key.to_owned(),
+                reason: format!("{value:?} is not a {key_type:?} value"),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+    s.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// A map from condition keys, such as "aws:SourceIp", to their values in a request.
+///
+/// Keys are matched case-insensitively. Each key may have several values.
+///
+/// Any key may be set, including service-specific keys such as `s3:prefix`, but the
+/// values of well-known global keys are checked to be of the right type when a
+/// request is loaded, and there are typed accessors for them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Context(BTreeMap<String, Vec<String>>);
+
+impl Context {
+    /// Make a new empty context.
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Return the values of a key, looking it up case-insensitively, or None if
+    /// the key is not present.
+    pub fn get(&self, key: &str) -> Option<&[String]> {
+        self.0
+            .get(key)
+            .or_else(|| {
+                self.0
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v)
+            })
+            .map(Vec::as_slice)
+    }
+
+    /// Return the value of a single-valued key, or None if it is not present.
+    pub fn get_one(&self, key: &str) -> Option<&str> {
+        self.get(key)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+
+    /// Check that the values of every key are acceptable, as for [validate].
+    pub fn validate(&self) -> Result<()> {
+        self.0
+            .iter()
+            .try_for_each(|(key, values)| validate(key, values))
+    }
+
+    /// True if the key is present.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Set the values of a key, replacing any previous values.
+    ///
+    /// The values are not checked: see [validate].
+    pub fn insert<K, V>(&mut self, key: K, values: V)
+    where
+        K: Into<String>,
+        V: IntoIterator,
+        V::Item: Into<String>,
+    {
+        let key = key.into();
+        self.0.retain(|k, _| !k.eq_ignore_ascii_case(&key));
+        self.0
+            .insert(key, values.into_iter().map(Into::into).collect());
+    }
+
+    /// Set the values of a key only if it is not already present.
+    pub fn insert_if_absent<K, V>(&mut self, key: K, values: V)
+    where
+        K: Into<String>,
+        V: IntoIterator,
+        V::Item: Into<String>,
+    {
+        let key = key.into();
+        if !self.contains_key(&key) {
+            self.insert(key, values);
+        }
+    }
+
+    /// Iterate all keys and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// The source IP address (`aws:SourceIp`).
+    pub fn source_ip(&self) -> Option<IpAddr> {
+        self.get_one(SOURCE_IP)?.parse().ok()
+    }
+
+    pub fn set_source_ip(&mut self, ip: IpAddr) {
+        self.insert(SOURCE_IP, [ip.to_string()]);
+    }
+
+    /// The time of the request (`aws:CurrentTime`), as an ISO 8601 string.
+    pub fn current_time(&self) -> Option<&str> {
+        self.get_one(CURRENT_TIME)
+    }
+
+    /// Set the time of the request (`aws:CurrentTime`) from an ISO 8601 string.
+    pub fn set_current_time(&mut self, time: &str) -> Result<()> {
+        let values = [time.to_owned()];
+        validate(CURRENT_TIME, &values)?;
+        self.insert(CURRENT_TIME, values);
+        Ok(())
+    }
+
+    /// Whether the request used TLS (`aws:SecureTransport`).
+    pub fn secure_transport(&self) -> Option<bool> {
+        self.get_one(SECURE_TRANSPORT)
+            .map(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    pub fn set_secure_transport(&mut self, secure: bool) {
+        self.insert(SECURE_TRANSPORT, [secure.to_string()]);
+    }
+
+    /// The value of a tag on the principal (`aws:PrincipalTag/<key>`).
+    pub fn principal_tag(&self, tag_key: &str) -> Option<&str> {
+        self.get_one(&format!("{PRINCIPAL_TAG_PREFIX}{tag_key}"))
+    }
+
+    pub fn set_principal_tag(&mut self, tag_key: &str, value: &str) {
+        self.insert(format!("{PRINCIPAL_TAG_PREFIX}{tag_key}"), [value]);
+    }
+
+    /// The value of a tag passed in the request (`aws:RequestTag/<key>`).
+    pub fn request_tag(&self, tag_key: &str) -> Option<&str> {
+        self.get_one(&format!("{REQUEST_TAG_PREFIX}{tag_key}"))
+    }
+
+    pub fn set_request_tag(&mut self, tag_key: &str, value: &str) {
+        self.insert(format!("{REQUEST_TAG_PREFIX}{tag_key}"), [value]);
+    }
+
+    /// The keys of tags passed in the request (`aws:TagKeys`).
+    pub fn tag_keys(&self) -> Option<&[String]> {
+        self.get(TAG_KEYS)
+    }
+
+    pub fn set_tag_keys<V>(&mut self, tag_keys: V)
+    where
+        V: IntoIterator,
+        V::Item: Into<String>,
+    {
+        self.insert(TAG_KEYS, tag_keys);
+    }
+}
+
+/// Keys with a single value are serialized as just that string, and others as a list.
+impl Serialize for Context {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, values) in &self.0 {
+            match values.as_slice() {
+                [value] => map.serialize_entry(key, value)?,
+                values => map.serialize_entry(key, values)?,
+            }
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Context {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map = BTreeMap::<String, ScalarOrList>::deserialize(deserializer)?;
+        let mut context = Context::new();
+        for (key, ScalarOrList(values)) in map {
+            if context.contains_key(&key) {
+                return Err(de::Error::custom(format!(
+                    "condition key {key:?} is repeated with different case"
+                )));
+            }
+            context.0.insert(key, values);
+        }
+        Ok(context)
+    }
+}
+
+impl JsonSchema for Context {
+    fn schema_name() -> String {
+        "Context".to_owned()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        fn value_schema(key_type: KeyType, multi_valued: bool) -> Value {
+            let scalar = match key_type {
+                KeyType::String => json!({ "type": "string" }),
+                KeyType::Bool => json!({ "type": ["boolean", "string"] }),
+                KeyType::Date => json!({ "type": ["string", "integer"] }),
+                KeyType::Numeric => json!({ "type": ["number", "string"] }),
+                KeyType::IpAddress => json!({ "type": "string", "format": "ip" }),
+            };
+            if multi_valued {
+                json!({ "anyOf": [scalar, { "type": "array", "items": scalar }] })
+            } else {
+                scalar
+            }
+        }
+
+        let properties = KNOWN_KEYS
+            .iter()
+            .map(|(key, key_type, multi_valued)| {
+                (key.to_string(), value_schema(*key_type, *multi_valued))
+            })
+            .collect::<Map<String, Value>>();
+        let pattern_properties = TAG_PREFIXES
+            .iter()
+            .map(|prefix| {
+                (
+                    format!("^{}", prefix.replace('/', "\\/")),
+                    value_schema(KeyType::String, false),
+                )
+            })
+            .collect::<Map<String, Value>>();
+        serde_json::from_value(json!({
+            "description": "Condition keys and their values, such as \"aws:SourceIp\": \"203.0.113.7\". \
+                Keys may have a single value or a list of values.",
+            "type": "object",
+            "properties": properties,
+            "patternProperties": pattern_properties,
+            "additionalProperties": {
+                "anyOf": [
+                    { "type": ["string", "boolean", "number"] },
+                    { "type": "array", "items": { "type": ["string", "boolean", "number"] } }
+                ]
+            }
+        }))
+        .expect("Context schema is valid")
+    }
+}
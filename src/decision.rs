@@ -0,0 +1,126 @@
+// Copyright 2023 Martin Pool
+
+//! The result of evaluating a request, and an explanation of why.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::effect::Effect;
+use crate::policy::PolicyType;
+
+/// The result of evaluating a request against a scenario, along with the statements
+/// that matched it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Decision {
+    /// Whether the request is allowed, and if not, whether it was denied explicitly.
+    pub outcome: Outcome,
+
+    /// The step of the evaluation logic that determined the result, e.g.
+    /// "Not allowed by the permissions boundary".
+    pub reason: String,
+
+    /// Every statement, in any applicable policy, that matched the request.
+    pub matched_statements: Vec<MatchedStatement>,
+}
+
+/// The outcome of evaluating a request.
+///
+/// See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html>.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The request is allowed.
+    Allow,
+    /// The request is denied by a `Deny` statement in some applicable policy.
+    ExplicitDeny,
+    /// The request is denied because the policies that must allow it did not.
+    ImplicitDeny,
+}
+
+/// A statement that matched a request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct MatchedStatement {
+    /// The name of the policy containing the statement.
+    pub policy_name: String,
+
+    /// The type of the policy.
+    pub policy_type: PolicyType,
+
+    /// The index of the statement within the policy, counting from 0.
+    pub statement_index: usize,
+
+    /// The statement's `Sid`, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+
+    /// The statement's effect.
+    pub effect: Effect,
+}
+
+impl Decision {
+    /// The effect of the decision, without distinguishing explicit and implicit denies.
+    pub fn effect(&self) -> Effect {
+        self.outcome.effect()
+    }
+
+    pub fn is_allow(&self) -> bool {
+        self.outcome == Outcome::Allow
+    }
+
+    pub fn is_deny(&self) -> bool {
+        !self.is_allow()
+    }
+
+    /// The matched statements with the same effect as the decision: the statements
+    /// that allowed the request, or, for an explicit deny, those that denied it.
+    pub fn deciding_statements(&self) -> impl Iterator<Item = &MatchedStatement> {
+        self.matched_statements
+            .iter()
+            .filter(|statement| statement.effect == self.effect())
+    }
+}
+
+impl Outcome {
+    pub fn effect(&self) -> Effect {
+        match self {
+            Outcome::Allow => Effect::Allow,
+            Outcome::ExplicitDeny | Outcome::ImplicitDeny => Effect::Deny,
+        }
+    }
+}
+
+/// Describe the decision on one line, naming the statements that caused it.
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.outcome, self.reason)?;
+        for (i, statement) in self.deciding_statements().enumerate() {
+            f.write_str(if i == 0 { ": " } else { ", " })?;
+            write!(f, "{statement}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Describe the outcome, e.g. "Explicit deny".
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Allow => "Allow",
+            Outcome::ExplicitDeny => "Explicit deny",
+            Outcome::ImplicitDeny => "Implicit deny",
+        })
+    }
+}
+
+/// Describe the statement, e.g. `statement 0 ("AllowRead") of identity policy "ReadOnly"`.
+impl fmt::Display for MatchedStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "statement {}", self.statement_index)?;
+        if let Some(sid) = &self.sid {
+            write!(f, " ({sid:?})")?;
+        }
+        write!(f, " of {} policy {:?}", self.policy_type, self.policy_name)
+    }
+}
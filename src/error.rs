@@ -0,0 +1,168 @@
+// Copyright 2023 Martin Pool
+
+//! Errors from loading and evaluating policies, scenarios and test cases.
+
+use std::fmt;
+use std::io;
+
+use camino::Utf8PathBuf;
+
+/// An error from iamthat.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A file could not be read.
+    #[error("Failed to read {path}: {error}")]
+    Io { path: Utf8PathBuf, error: io::Error },
+
+    /// JSON could not be parsed, or does not have the expected structure.
+    #[error(
+        "Failed to parse JSON{}{}: {error}",
+        from_path(path),
+        at_pointer(pointer)
+    )]
+    Parse {
+        /// The file containing the JSON, if it was read from a file.
+        path: Option<Utf8PathBuf>,
+        /// A JSON pointer to the value that could not be parsed, such as
+        /// `/Statement/0/Effect`, or empty if the error is in the whole document.
+        pointer: String,
+        error: serde_json::Error,
+    },
+
+    /// An action or action glob, such as `s3:Get*`, is not valid.
+    #[error("Invalid action {action:?}: {reason}")]
+    InvalidAction { action: String, reason: String },
+
+    /// An ARN is not valid.
+    #[error("Invalid ARN {arn:?}: {reason}")]
+    InvalidArn { arn: String, reason: String },
+
+    /// A condition operator, such as `StringEqualsIfExists`, is not valid.
+    #[error("Invalid condition operator {operator:?}: {reason}")]
+    InvalidConditionOperator { operator: String, reason: String },
+
+    /// The values given for a key in the request context are not valid for that key.
+    #[error("Invalid value for condition key {key:?}: {reason}")]
+    InvalidContextValue { key: String, reason: String },
+
+    /// Something in a scenario refers to a policy, group or role that the scenario
+    /// does not define.
+    #[error("{}", describe_undefined(referrer.as_deref(), *kind, name))]
+    Undefined {
+        /// What holds the reference, such as `User "dev"`, if anything.
+        referrer: Option<String>,
+        kind: ReferenceKind,
+        /// The name of the missing policy, group or role.
+        name: String,
+    },
+
+    /// A service principal tried to assume a role, which only principals with an
+    /// ARN, such as users and role sessions, can do.
+    #[error("Service principal {service:?} can't assume roles")]
+    AssumeRoleFromService { service: String },
+}
+
+/// The kind of thing named by a reference within a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    AttachedPolicy,
+    Group,
+    PermissionsBoundary,
+    ResourceControlPolicy,
+    Role,
+    ServiceControlPolicy,
+    SessionPolicy,
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReferenceKind::AttachedPolicy => "attached policy",
+            ReferenceKind::Group => "group",
+            ReferenceKind::PermissionsBoundary => "permissions boundary",
+            ReferenceKind::ResourceControlPolicy => "resource control policy",
+            ReferenceKind::Role => "role",
+            ReferenceKind::ServiceControlPolicy => "service control policy",
+            ReferenceKind::SessionPolicy => "session policy",
+        })
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// An error parsing JSON from a file, or if `path` is None, from memory.
+    pub(crate) fn parse(
+        path: Option<Utf8PathBuf>,
+        error: serde_path_to_error::Error<serde_json::Error>,
+    ) -> Error {
+        Error::Parse {
+            path,
+            pointer: json_pointer(error.path()),
+            error: error.into_inner(),
+        }
+    }
+
+    pub(crate) fn undefined(
+        referrer: Option<String>,
+        kind: ReferenceKind,
+        name: impl Into<String>,
+    ) -> Error {
+        Error::Undefined {
+            referrer,
+            kind,
+            name: name.into(),
+        }
+    }
+}
+
+/// Convert a path within a deserialized document to a JSON pointer,
+/// per <https://www.rfc-editor.org/rfc/rfc6901>.
+fn json_pointer(path: &serde_path_to_error::Path) -> String {
+    use serde_path_to_error::Segment;
+    let mut pointer = String::new();
+    for segment in path {
+        match segment {
+            Segment::Seq { index } => pointer.push_str(&format!("/{index}")),
+            Segment::Map { key } | Segment::Enum { variant: key } => {
+                pointer.push('/');
+                pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+            }
+            // Within untagged values the path is not known.
+            Segment::Unknown => (),
+        }
+    }
+    pointer
+}
+
+fn from_path(path: &Option<Utf8PathBuf>) -> String {
+    match path {
+        Some(path) => format!(" from {path}"),
+        None => String::new(),
+    }
+}
+
+fn at_pointer(pointer: &str) -> String {
+    if pointer.is_empty() {
+        String::new()
+    } else {
+        format!(" at {pointer}")
+    }
+}
+
+fn describe_undefined(referrer: Option<&str>, kind: ReferenceKind, name: &str) -> String {
+    match referrer {
+        Some(referrer) => {
+            format!("{referrer} has {kind} {name:?} which is not defined in the scenario")
+        }
+        None => {
+            let kind = kind.to_string();
+            let (first, rest) = kind.split_at(1);
+            format!(
+                "{}{rest} {name:?} is not defined in the scenario",
+                first.to_uppercase()
+            )
+        }
+    }
+}
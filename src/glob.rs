@@ -0,0 +1,112 @@
+// Copyright 2023 Martin Pool
+
+//! Wildcard matching as used in IAM resource ARNs and `...Like` conditions.
+
+use std::fmt;
+
+/// One character of a wildcard [Pattern].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobChar {
+    /// Matches exactly this character.
+    Literal(char),
+    /// `*`: matches any sequence of characters, including none.
+    Star,
+    /// `?`: matches any single character.
+    Question,
+}
+
+/// A wildcard pattern in which `*` matches any sequence of characters and `?`
+/// matches any single character.
+///
+/// Patterns are usually parsed from strings, but after substitution of policy
+/// variables they may also contain literal `*` and `?` characters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pattern(pub Vec<GlobChar>);
+
+impl Pattern {
+    /// Parse a pattern in which every `*` and `?` is a wildcard.
+    pub fn new(pattern: &str) -> Pattern {
+        Pattern(
+            pattern
+                .chars()
+                .map(|c| match c {
+                    '*' => GlobChar::Star,
+                    '?' => GlobChar::Question,
+                    c => GlobChar::Literal(c),
+                })
+                .collect(),
+        )
+    }
+
+    /// Make a pattern that matches only exactly this string.
+    pub fn literal(s: &str) -> Pattern {
+        Pattern(s.chars().map(GlobChar::Literal).collect())
+    }
+
+    /// True if this pattern is just `*`.
+    pub fn is_star(&self) -> bool {
+        self.0 == [GlobChar::Star]
+    }
+
+    /// Test whether a string matches this pattern. Matching is case-sensitive.
+    pub fn matches(&self, value: &str) -> bool {
+        glob_chars_match(&self.0, value)
+    }
+}
+
+/// Shows the pattern with wildcards and literal characters both written as
+/// themselves, which is the string to use for exact comparisons.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.0 {
+            match c {
+                GlobChar::Literal(c) => write!(f, "{c}")?,
+                GlobChar::Star => f.write_str("*")?,
+                GlobChar::Question => f.write_str("?")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Match a string against a pattern in which `*` matches any sequence of
+/// characters (including none) and `?` matches any single character.
+///
+/// Matching is case-sensitive.
+pub fn wildcard_match(pattern: &str, value: &str) -> bool {
+    Pattern::new(pattern).matches(value)
+}
+
+fn glob_chars_match(pattern: &[GlobChar], value: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // Position in the pattern just after the last `*` seen, and the position in the value
+    // that star is currently assumed to extend to.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some(GlobChar::Star) => {
+                p += 1;
+                backtrack = Some((p, v));
+            }
+            Some(GlobChar::Question) => {
+                p += 1;
+                v += 1;
+            }
+            Some(GlobChar::Literal(c)) if *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                // Let the last star swallow one more character and try again.
+                Some((star_p, star_v)) => {
+                    p = star_p;
+                    v = star_v + 1;
+                    backtrack = Some((star_p, star_v + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == GlobChar::Star)
+}
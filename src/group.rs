@@ -0,0 +1,56 @@
+// Copyright 2023 Martin Pool
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::policy::Policy;
+use crate::user::slash;
+
+/// An IAM group, whose policies apply to all its member users.
+///
+/// `P` is the type of inline policies, as for [crate::user::User].
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    rename_all = "PascalCase",
+    bound(deserialize = "P: Deserialize<'de>")
+)]
+pub struct Group<P = Policy> {
+    /// The group's name.
+    pub group_name: String,
+
+    /// The group's path, e.g. `/eng/`.
+    #[serde(default = "slash")]
+    pub path: String,
+
+    /// Named policies attached to this group.
+    #[serde(default)]
+    pub attached_policies: Vec<String>,
+
+    /// Policies embedded in this group, indexed by name.
+    #[serde(default)]
+    pub inline_policies: HashMap<String, P>,
+}
+
+impl<P> Default for Group<P> {
+    fn default() -> Self {
+        Group {
+            group_name: String::new(),
+            path: slash(),
+            attached_policies: Vec::new(),
+            inline_policies: HashMap::new(),
+        }
+    }
+}
+
+impl<P> Group<P> {
+    /// The group's ARN, given the account that contains it.
+    pub fn arn(&self, account_id: &str) -> String {
+        format!(
+            "arn:aws:iam::{account_id}:group{}{}",
+            self.path, self.group_name
+        )
+    }
+}
@@ -2,19 +2,29 @@
 
 use std::fmt;
 use std::fs::read_to_string;
+use std::marker::PhantomData;
 
 use camino::Utf8Path;
-use eyre::Context;
-use serde::de::{self, Visitor};
-use serde::Deserializer;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{self, IntoDeserializer, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
 
+use crate::error::{Error, Result};
+
+/// Load a value from JSON.
+///
+/// Parse errors carry a JSON pointer to the value that could not be parsed, and the
+/// path of the file if it was read from a file.
 pub trait FromJson: Sized + serde::de::DeserializeOwned {
-    fn from_json(json: &str) -> eyre::Result<Self>;
+    fn from_json(json: &str) -> Result<Self>;
 
-    fn from_json_file(path: &Utf8Path) -> eyre::Result<Self>;
+    fn from_json_file(path: &Utf8Path) -> Result<Self>;
 
-    fn from_json_value(value: serde_json::Value) -> eyre::Result<Self> {
-        serde_json::from_value(value).wrap_err("Failed to parse JSON")
+    fn from_json_value(value: serde_json::Value) -> Result<Self> {
+        serde_path_to_error::deserialize(value).map_err(|err| Error::parse(None, err))
     }
 }
 
@@ -22,57 +32,286 @@ impl<T> FromJson for T
 where
     T: Sized + serde::de::DeserializeOwned,
 {
-    fn from_json(json: &str) -> eyre::Result<Self> {
-        serde_json::from_str(json).wrap_err("Failed to parse JSON")
+    fn from_json(json: &str) -> Result<Self> {
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(json))
+            .map_err(|err| Error::parse(None, err))
     }
 
-    fn from_json_file(path: &Utf8Path) -> eyre::Result<Self> {
-        serde_json::from_str(
-            read_to_string(path)
-                .wrap_err_with(|| format!("failed to read file {path:?}"))?
-                .as_str(),
-        )
-        .wrap_err_with(|| format!("failed to parse JSON from {path:?}"))
+    fn from_json_file(path: &Utf8Path) -> Result<Self> {
+        let json = read_to_string(path).map_err(|error| Error::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(&json))
+            .map_err(|err| Error::parse(Some(path.to_owned()), err))
     }
 }
 
-/// Deserialize either a single string or a list of strings.
+/// A string, boolean or number, or a list of them, all converted to strings.
+///
+/// This is the form of values in `Condition` blocks and in the request context,
+/// where for example `"aws:SecureTransport": false` means the same as
+/// `"aws:SecureTransport": ["false"]`.
+pub(crate) struct ScalarOrList(pub Vec<String>);
+
+impl<'de> Deserialize<'de> for ScalarOrList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_any(ScalarVisitor { allow_list: true })
+            .map(ScalarOrList)
+    }
+}
+
+impl JsonSchema for ScalarOrList {
+    fn schema_name() -> String {
+        "ScalarOrList".to_owned()
+    }
+
+    fn json_schema(_gen: &mut SchemaGenerator) -> Schema {
+        let scalar = json!({ "type": ["string", "boolean", "number"] });
+        serde_json::from_value(json!({
+            "description": "A string, boolean or number, or a list of them.",
+            "anyOf": [scalar, { "type": "array", "items": scalar }],
+        }))
+        .expect("valid schema")
+    }
+}
+
+/// One element of a [ScalarOrList].
+struct Scalar(String);
+
+impl<'de> Deserialize<'de> for Scalar {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut v = deserializer.deserialize_any(ScalarVisitor { allow_list: false })?;
+        Ok(Scalar(v.remove(0)))
+    }
+}
+
+struct ScalarVisitor {
+    allow_list: bool,
+}
+
+impl<'de> Visitor<'de> for ScalarVisitor {
+    type Value = Vec<String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.allow_list {
+            formatter.write_str("string, boolean or number, or a list of them")
+        } else {
+            formatter.write_str("string, boolean or number")
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        if !self.allow_list {
+            return Err(de::Error::invalid_type(de::Unexpected::Seq, &self));
+        }
+        let mut v = Vec::new();
+        while let Some(Scalar(el)) = seq.next_element()? {
+            v.push(el)
+        }
+        Ok(v)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(vec![value.to_owned()])
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(vec![value.to_string()])
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(vec![value.to_string()])
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(vec![value.to_string()])
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(vec![value.to_string()])
+    }
+}
+
+/// A single string, or a list of strings, remembering which form was used so that
+/// it can be written back the same way.
 ///
 /// Many places in the IAM grammar allow a list of one string to be
 /// abbreviated as just a string.
-pub(crate) fn de_string_or_list<'de, D>(
-    deserializer: D,
-) -> std::result::Result<Vec<String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    // Like <https://serde.rs/string-or-struct.html>
-    struct StringOrList();
-    impl<'de> Visitor<'de> for StringOrList {
-        type Value = Vec<String>;
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
+#[serde(untagged)]
+pub enum StringOrList {
+    String(String),
+    List(Vec<String>),
+}
 
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("string or list of strings")
+impl StringOrList {
+    /// The strings, whichever form they were written in.
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            StringOrList::String(s) => std::slice::from_ref(s),
+            StringOrList::List(v) => v,
         }
+    }
 
-        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-        where
-            A: de::SeqAccess<'de>,
-        {
-            let mut v = Vec::new();
-            while let Some(el) = seq.next_element()? {
-                v.push(el)
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            StringOrList::String(s) => vec![s],
+            StringOrList::List(v) => v,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StringOrList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct StringOrListVisitor;
+        impl<'de> Visitor<'de> for StringOrListVisitor {
+            type Value = StringOrList;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("string or list of strings")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut v = Vec::new();
+                while let Some(el) = seq.next_element()? {
+                    v.push(el)
+                }
+                Ok(StringOrList::List(v))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(StringOrList::String(value.to_owned()))
             }
-            Ok(v)
         }
 
-        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        deserializer.deserialize_any(StringOrListVisitor)
+    }
+}
+
+/// One value or a list of values, each parsed from a string, such as the action
+/// globs in an `Action` element.
+///
+/// A string that can't be parsed is reported at its own position in the list.
+pub(crate) struct OneOrList<T>(pub Vec<T>);
+
+impl<'de, T> Deserialize<'de> for OneOrList<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OneOrListVisitor<T>(PhantomData<T>);
+        impl<'de, T> Visitor<'de> for OneOrListVisitor<T>
         where
-            E: de::Error,
+            T: Deserialize<'de>,
         {
-            Ok(vec![value.to_owned()])
+            type Value = OneOrList<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("string or list of strings")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut v = Vec::new();
+                while let Some(el) = seq.next_element()? {
+                    v.push(el)
+                }
+                Ok(OneOrList(v))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                T::deserialize(value.into_deserializer()).map(|v| OneOrList(vec![v]))
+            }
         }
+
+        deserializer.deserialize_any(OneOrListVisitor(PhantomData))
+    }
+}
+
+/// In JSON this is a string or list of strings, whatever the strings are parsed as.
+impl<T> JsonSchema for OneOrList<T> {
+    fn is_referenceable() -> bool {
+        false
     }
 
-    deserializer.deserialize_any(StringOrList())
+    fn schema_name() -> String {
+        StringOrList::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        gen.subschema_for::<StringOrList>()
+    }
+}
+
+/// Take whichever of a pair of mutually exclusive elements, such as `Principal` and
+/// `NotPrincipal`, is present.
+///
+/// Returns the index of the element that was present and its value, or None if
+/// neither was.
+pub(crate) fn at_most_one_of<T>(
+    names: [&str; 2],
+    values: [Option<T>; 2],
+) -> std::result::Result<Option<(usize, T)>, String> {
+    match values {
+        [Some(_), Some(_)] => Err(format!(
+            "`{}` and `{}` may not both be present",
+            names[0], names[1]
+        )),
+        [Some(value), None] => Ok(Some((0, value))),
+        [None, Some(value)] => Ok(Some((1, value))),
+        [None, None] => Ok(None),
+    }
+}
+
+/// Like [at_most_one_of], but one of the elements, such as `Action` or `NotAction`,
+/// is required.
+pub(crate) fn exactly_one_of<T>(
+    names: [&str; 2],
+    values: [Option<T>; 2],
+) -> std::result::Result<(usize, T), String> {
+    at_most_one_of(names, values)?
+        .ok_or_else(|| format!("one of `{}` or `{}` must be present", names[0], names[1]))
 }
@@ -1,18 +1,28 @@
 // Copyright 2023 Martin Pool
 
 pub mod action;
+pub mod arn;
+pub mod assume;
+pub mod condition;
+pub mod context;
+pub mod decision;
 pub mod effect;
+pub mod error;
+pub mod glob;
+pub mod group;
 pub mod json;
+pub mod organization;
 pub mod policy;
 pub mod principal;
 pub mod request;
+pub mod resource;
+pub mod role;
 pub mod scenario;
 pub mod tag;
 pub mod testcase;
+pub mod trace;
 pub mod user;
+pub mod variable;
 
+pub use error::{Error, Result};
 pub use request::Request;
-
-// TODO: Maybe a more specific and structured error type, to make this
-// more usable as a library?
-pub use eyre::{Error, Result};
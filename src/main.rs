@@ -10,18 +10,24 @@ use std::process::ExitCode;
 use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand, ValueEnum};
 use eyre::Context;
-use iamthat::policy::Policy;
+use iamthat::assume::{AssumeRoleRequest, Session};
+use iamthat::group::Group;
+use iamthat::policy::{Policy, SessionPolicy};
+use iamthat::principal::Principal;
 use iamthat::tag::Tag;
 use iamthat::testcase::{AssertionResult, TestCase, TestCaseWithPaths};
 use iamthat::user::User;
 use schemars::schema_for;
+use serde::Serialize;
 use tracing::{info, trace};
 use tracing_subscriber::prelude::*;
 
-use iamthat::effect::Effect;
+use iamthat::decision::Decision;
 use iamthat::json::FromJson;
 use iamthat::request::Request;
+use iamthat::role::Role;
 use iamthat::scenario::{Scenario, ScenarioWithPaths};
+use iamthat::trace::Trace;
 use iamthat::Result;
 
 #[derive(Parser, Debug)]
@@ -36,6 +42,46 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 enum Command {
+    /// Simulate a chain of `sts:AssumeRole` calls, each assuming the next role
+    /// from the session created by the previous one.
+    Assume {
+        /// The scenario file containing the roles
+        #[arg(long, short, required = true)]
+        scenario: Utf8PathBuf,
+
+        /// The ARN of the principal that assumes the first role
+        #[arg(long, short, required = true)]
+        principal: String,
+
+        /// Names or ARNs of the roles to assume, in order
+        #[arg(required = true)]
+        roles: Vec<String>,
+
+        /// The session name used for every role
+        #[arg(long)]
+        session_name: Option<String>,
+
+        /// Session tags passed when assuming the first role, as KEY=VALUE
+        #[arg(long, value_parser = parse_tag)]
+        tag: Vec<Tag>,
+
+        /// Keys of the session tags that are transitive
+        #[arg(long)]
+        transitive_tag_key: Vec<String>,
+
+        /// ARNs of managed session policies passed when assuming the last role
+        #[arg(long)]
+        session_policy_arn: Vec<String>,
+
+        /// Files containing inline session policies passed when assuming the last role
+        #[arg(long)]
+        session_policy: Vec<Utf8PathBuf>,
+
+        /// Write the outcome of each hop, and the final session, as json to this file.
+        #[arg(long, short)]
+        output: Option<Utf8PathBuf>,
+    },
+
     /// Evaluate a request against against the policies
     /// in a scenario file, and print the results.
     Eval {
@@ -50,6 +96,11 @@ enum Command {
         /// Write evaluation results as json to this file.
         #[arg(long, short)]
         output: Option<Utf8PathBuf>,
+
+        /// Print how every statement of every applicable policy was evaluated,
+        /// as text or json.
+        #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text")]
+        trace: Option<TraceFormat>,
     },
 
     /// Emit json schemas for all file types defined by iamthat.
@@ -69,6 +120,23 @@ enum Command {
     },
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum TraceFormat {
+    Text,
+    Json,
+}
+
+/// The decision on one request, and how it was reached, as printed by
+/// `eval --trace=json`.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct TracedDecision<'a> {
+    /// The file the request was read from.
+    request: &'a Utf8PathBuf,
+    decision: &'a Decision,
+    trace: &'a Trace,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum SchemaType {
     Request,
@@ -80,29 +148,117 @@ fn main() -> eyre::Result<ExitCode> {
     init_tracing(args.json_log.as_ref());
 
     match args.command {
+        Command::Assume {
+            scenario,
+            principal,
+            roles,
+            session_name,
+            tag,
+            transitive_tag_key,
+            session_policy_arn,
+            session_policy,
+            output,
+        } => {
+            let scenario = Scenario::from_json_file(&scenario)
+                .wrap_err_with(|| format!("failed to read scenario file {scenario:?}"))?;
+            let mut steps = roles
+                .iter()
+                .map(|role| AssumeRoleRequest {
+                    role_session_name: session_name.clone(),
+                    ..AssumeRoleRequest::new(role)
+                })
+                .collect::<Vec<_>>();
+            steps[0].tags = tag;
+            steps[0].transitive_tag_keys = transitive_tag_key;
+            let last = steps.last_mut().expect("At least one role");
+            last.session_policies
+                .extend(session_policy_arn.into_iter().map(SessionPolicy::Arn));
+            for path in &session_policy {
+                last.session_policies.push(SessionPolicy::Inline(
+                    Policy::from_json_file(path)
+                        .wrap_err_with(|| format!("Failed to read session policy {path:?}"))?,
+                ));
+            }
+            let chain =
+                scenario.assume_role_chain(Session::new(Principal::ARN(principal)), &steps)?;
+            for hop in &chain.hops {
+                match &hop.denied_because {
+                    None => println!("{}: Allow", hop.role_arn),
+                    Some(reason) => println!("{}: Deny: {reason}", hop.role_arn),
+                }
+            }
+            if let Some(arn) = chain.session.as_ref().and_then(|s| s.principal.arn()) {
+                println!("Session: {arn}");
+            }
+            if let Some(out_path) = output {
+                let mut out = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(&out_path)
+                    .wrap_err_with(|| format!("failed to open output file {out_path:?}"))?;
+                serde_json::to_writer_pretty(&mut out, &chain)?;
+                writeln!(out)?;
+            }
+            if chain.is_allowed() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
         Command::Eval {
             output,
             scenario,
-            request,
+            request: request_paths,
+            trace,
         } => {
             let scenario = Scenario::from_json_file(&scenario)
                 .wrap_err_with(|| format!("failed to read scenario file {scenario:?}"))?;
             info!(?scenario);
-            let requests = request
+            let requests = request_paths
                 .iter()
                 .map(|p| {
                     Request::from_json_file(p)
                         .wrap_err_with(|| format!("Failed to read request file {p:?}"))
                 })
                 .collect::<eyre::Result<Vec<Request>>>()?;
-            let effects = requests
-                .into_iter()
+            let decisions = requests
+                .iter()
                 .map(|request| {
-                    let effect = scenario.eval(&request);
-                    info!(?request, ?effect);
-                    effect
+                    let decision = scenario.eval(request);
+                    info!(?request, ?decision);
+                    decision
                 })
                 .collect::<Result<Vec<_>>>()?;
+            if let Some(format) = trace {
+                let traces = requests
+                    .iter()
+                    .map(|request| scenario.trace(request))
+                    .collect::<Result<Vec<_>>>()?;
+                match format {
+                    TraceFormat::Text => {
+                        for ((path, decision), trace) in
+                            request_paths.iter().zip(&decisions).zip(&traces)
+                        {
+                            println!("{path}: {decision}");
+                            print!("{trace}");
+                        }
+                    }
+                    TraceFormat::Json => {
+                        let traced = request_paths
+                            .iter()
+                            .zip(&decisions)
+                            .zip(&traces)
+                            .map(|((request, decision), trace)| TracedDecision {
+                                request,
+                                decision,
+                                trace,
+                            })
+                            .collect::<Vec<_>>();
+                        println!("{}", serde_json::to_string_pretty(&traced)?);
+                    }
+                }
+            }
             if let Some(out_path) = output {
                 let mut out = OpenOptions::new()
                     .create(true)
@@ -110,16 +266,18 @@ fn main() -> eyre::Result<ExitCode> {
                     .truncate(true)
                     .open(&out_path)
                     .wrap_err_with(|| format!("failed to open output file {out_path:?}"))?;
-                serde_json::to_writer_pretty(&mut out, &effects)?;
+                serde_json::to_writer_pretty(&mut out, &decisions)?;
                 writeln!(out)?;
                 out.flush()?;
             }
-            results_to_return_code(&effects)
+            results_to_return_code(&decisions)
         }
         Command::JsonSchema { out_dir } => {
             for (name, schema) in [
+                ("group", schema_for!(Group)),
                 ("policy", schema_for!(Policy)),
                 ("request", schema_for!(Request)),
+                ("role", schema_for!(Role)),
                 ("scenario", schema_for!(ScenarioWithPaths)),
                 ("tag", schema_for!(Tag)),
                 ("testcase", schema_for!(TestCaseWithPaths)),
@@ -158,6 +316,19 @@ fn main() -> eyre::Result<ExitCode> {
                 serde_json::to_writer_pretty(&mut out, &results)?;
                 writeln!(out)?;
             }
+            for (path, testcase_results) in testcase_paths.iter().zip(&results) {
+                for (i, result) in testcase_results.iter().enumerate() {
+                    match result {
+                        AssertionResult::Pass => (),
+                        AssertionResult::Fail(decision) => {
+                            println!("{path}: assertion {i} failed: {decision}")
+                        }
+                        AssertionResult::Error(err) => {
+                            println!("{path}: assertion {i} error: {err}")
+                        }
+                    }
+                }
+            }
             if results.iter().flatten().all(AssertionResult::is_pass) {
                 Ok(ExitCode::SUCCESS)
             } else {
@@ -167,8 +338,19 @@ fn main() -> eyre::Result<ExitCode> {
     }
 }
 
-fn results_to_return_code(results: &[Effect]) -> Result<ExitCode, eyre::ErrReport> {
-    if results.iter().any(Effect::is_deny) {
+/// Parse a tag given on the command line as `KEY=VALUE`.
+fn parse_tag(s: &str) -> eyre::Result<Tag> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("Tag {s:?} is not in the form KEY=VALUE"))?;
+    Ok(Tag {
+        key: key.to_owned(),
+        value: value.to_owned(),
+    })
+}
+
+fn results_to_return_code(results: &[Decision]) -> Result<ExitCode, eyre::ErrReport> {
+    if results.iter().any(Decision::is_deny) {
         info!("Some requests were denied");
         Ok(ExitCode::FAILURE) // TODO: More specific for "success but denied"
     } else {
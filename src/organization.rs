@@ -0,0 +1,127 @@
+// Copyright 2023 Martin Pool
+
+//! An AWS Organization: a tree of organizational units containing accounts, with
+//! service control policies and resource control policies attached at each level.
+//!
+//! See <https://docs.aws.amazon.com/organizations/latest/userguide/orgs_manage_policies_scps.html>
+//! and <https://docs.aws.amazon.com/organizations/latest/userguide/orgs_manage_policies_rcps.html>.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// An organization, containing the accounts whose principals are governed by its
+/// service control policies, and whose resources are governed by its resource
+/// control policies.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct Organization {
+    /// The id of the management account, whose principals and resources are not
+    /// affected by service control policies or resource control policies.
+    pub management_account_id: String,
+
+    /// The root of the organization.
+    pub root: OrganizationalUnit,
+}
+
+/// The root or an organizational unit (OU) within an organization.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct OrganizationalUnit {
+    /// The name of the OU, e.g. "Root" or "Workloads".
+    pub name: String,
+
+    /// The names of service control policies attached to this OU.
+    #[serde(default)]
+    pub service_control_policies: Vec<String>,
+
+    /// The names of resource control policies attached to this OU.
+    #[serde(default)]
+    pub resource_control_policies: Vec<String>,
+
+    /// Accounts directly within this OU.
+    #[serde(default)]
+    pub accounts: Vec<Account>,
+
+    /// OUs nested within this OU.
+    #[serde(default)]
+    pub organizational_units: Vec<OrganizationalUnit>,
+}
+
+/// An account within an organization.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct Account {
+    /// The 12-digit account id.
+    pub account_id: String,
+
+    /// The names of service control policies attached directly to this account.
+    #[serde(default)]
+    pub service_control_policies: Vec<String>,
+
+    /// The names of resource control policies attached directly to this account.
+    #[serde(default)]
+    pub resource_control_policies: Vec<String>,
+}
+
+/// One level of the path from the root of an organization to an account: the root,
+/// an OU, or the account itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrganizationLevel<'a> {
+    /// The name of the OU, or the account id.
+    pub name: &'a str,
+
+    /// The names of the service control policies attached at this level.
+    pub service_control_policies: &'a [String],
+
+    /// The names of the resource control policies attached at this level.
+    pub resource_control_policies: &'a [String],
+}
+
+impl Organization {
+    /// The levels from the root down to the given account, or None if the account
+    /// is not in the organization.
+    pub fn path_to_account(&self, account_id: &str) -> Option<Vec<OrganizationLevel<'_>>> {
+        let mut path = Vec::new();
+        self.root
+            .find_account(account_id, &mut path)
+            .then_some(path)
+    }
+
+    /// True if the account is the organization's management account.
+    pub fn is_management_account(&self, account_id: &str) -> bool {
+        self.management_account_id == account_id
+    }
+}
+
+impl OrganizationalUnit {
+    /// Search for an account within this OU, pushing each level onto `path` and
+    /// returning true if it is found.
+    fn find_account<'a>(&'a self, account_id: &str, path: &mut Vec<OrganizationLevel<'a>>) -> bool {
+        path.push(OrganizationLevel {
+            name: &self.name,
+            service_control_policies: &self.service_control_policies,
+            resource_control_policies: &self.resource_control_policies,
+        });
+        if let Some(account) = self
+            .accounts
+            .iter()
+            .find(|account| account.account_id == account_id)
+        {
+            path.push(OrganizationLevel {
+                name: &account.account_id,
+                service_control_policies: &account.service_control_policies,
+                resource_control_policies: &account.resource_control_policies,
+            });
+            return true;
+        }
+        if self
+            .organizational_units
+            .iter()
+            .any(|ou| ou.find_account(account_id, path))
+        {
+            return true;
+        }
+        path.pop();
+        false
+    }
+}
@@ -8,26 +8,42 @@
 // * <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_grammar.html>
 // * <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html>
 
-use std::str::FromStr;
+use std::borrow::Cow;
+use std::fmt;
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use serde_json::json;
+use tracing::debug;
 
 use crate::action::ActionGlob;
+use crate::arn::{arn_pattern_matches, ArnPattern};
+use crate::condition::Condition;
+use crate::context::Context;
 use crate::effect::Effect;
-use crate::json::de_string_or_list;
-use crate::principal::PrincipalOrNot;
+use crate::glob::Pattern;
+use crate::json::{exactly_one_of, OneOrList};
+use crate::principal::{Principal, PrincipalOrNot, Principals};
 use crate::request::Request;
+use crate::trace::{Element, ElementTrace, StatementTrace};
+use crate::variable::expands_variables;
 
 /// An IAM policy document, containing some statements.
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
-#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+#[serde(
+    from = "PolicyDocument",
+    deny_unknown_fields,
+    rename_all = "PascalCase"
+)]
 pub struct Policy {
     /// The version of the IAM grammar, "2008-10-17" or "2012-10-17".
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
     /// A user-supplied id for the policy. Some services have special
     /// constraints on the id.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
     pub statement: Vec<Statement>,
 }
@@ -44,12 +60,83 @@ impl Policy {
             .iter()
             .any(|statement| statement.denies(request))
     }
+
+    /// The statements that apply to the request, with their index in the policy.
+    pub fn matching_statements<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> impl Iterator<Item = (usize, &'a Statement)> + 'a {
+        self.statement
+            .iter()
+            .enumerate()
+            .filter(move |(_i, statement)| statement.matches(request))
+    }
+
+    /// Explain whether each statement matches the request, and why.
+    pub fn trace(&self, request: &Request) -> Vec<StatementTrace> {
+        self.statement
+            .iter()
+            .enumerate()
+            .map(|(i, statement)| statement.trace(i, request))
+            .collect()
+    }
+
+    /// True if an `Allow` statement matches the request and names the request's
+    /// principal by its exact ARN, as in a resource policy granting access to a
+    /// specific user or role session.
+    pub fn allows_named_principal(&self, request: &Request) -> bool {
+        self.statement.iter().any(|statement| {
+            statement
+                .principal
+                .as_ref()
+                .is_some_and(|principal| principal.names(&request.principal))
+                && statement.allows(request)
+        })
+    }
+
+    /// True if policy variables like `${aws:username}` are expanded in this policy,
+    /// which depends on its version.
+    pub fn expands_variables(&self) -> bool {
+        expands_variables(self.version.as_deref())
+    }
+}
+
+/// The JSON form of a [Policy], from which each statement learns whether policy
+/// variables are expanded.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+struct PolicyDocument {
+    version: Option<String>,
+    id: Option<String>,
+    statement: Vec<Statement>,
+}
+
+impl From<PolicyDocument> for Policy {
+    fn from(document: PolicyDocument) -> Policy {
+        let expands_variables = expands_variables(document.version.as_deref());
+        Policy {
+            statement: document
+                .statement
+                .into_iter()
+                .map(|statement| Statement {
+                    expands_variables,
+                    ..statement
+                })
+                .collect(),
+            version: document.version,
+            id: document.id,
+        }
+    }
 }
 
 /// One statement in a policy, stating that requests matching some conditions
 /// should be either allowed or denied.
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
-#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(
+    try_from = "StatementDocument",
+    deny_unknown_fields,
+    rename_all = "PascalCase"
+)]
 pub struct Statement {
     /// Statement id.
     ///
@@ -58,59 +145,486 @@ pub struct Statement {
     /// requirements for the Sid value. For example, some services require this value to be
     /// unique within an AWS account, and some services allow additional characters such as
     /// spaces in the Sid value."
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub sid: Option<String>,
 
-    /// The principal to which this statement applies.
+    /// The principals to which this statement applies, or to which it does not apply.
+    ///
+    /// This is only present in resource policies.
     #[serde(flatten)]
     pub principal: Option<PrincipalOrNot>,
 
     /// The effect of this statement: allow or deny.
     pub effect: Effect,
 
-    #[serde(deserialize_with = "de_string_or_list")]
-    pub resource: Vec<String>, // TODO: Or NotResource
+    /// The resources to which this statement applies, or to which it does not apply.
+    ///
+    /// This is required when a statement is deserialized, but is None in the
+    /// statements of a [TrustPolicy](crate::role::TrustPolicy), which apply only to
+    /// the role that holds them.
+    #[serde(flatten)]
+    pub resource: Option<ResourceOrNotResource>,
+
+    /// The actions to which this statement applies, or to which it does not apply.
+    #[serde(flatten)]
+    pub action: ActionOrNotAction,
+
+    /// Conditions on the request context that must all be satisfied for the
+    /// statement to apply.
+    #[serde(default, skip_serializing_if = "Condition::is_empty")]
+    pub condition: Condition,
+
+    /// True if policy variables like `${aws:username}` in resources and conditions
+    /// are expanded, which is set from the `Version` of the containing policy when
+    /// it's loaded. Otherwise, as in policies older than 2012-10-17 or a statement
+    /// loaded on its own, they're treated as literal text.
+    #[serde(skip)]
+    pub expands_variables: bool,
+}
+
+/// The JSON form of a [Statement], or of a statement in a trust policy, with each
+/// element as a separate field so that an error is reported at the element that
+/// holds it.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub(crate) struct StatementDocument {
+    /// Statement id, which in IAM policies may contain only the characters A-Z, a-z
+    /// and 0-9.
+    pub sid: Option<String>,
+    /// The principals to which this statement applies, in a resource policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Principals")]
+    pub principal: Option<Principals>,
+    /// The principals to which this statement does not apply, in a resource policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Principals")]
+    pub not_principal: Option<Principals>,
+    /// The effect of this statement: allow or deny.
+    pub effect: Effect,
+    /// The actions to which this statement applies.
+    #[serde(default)]
+    #[schemars(with = "OneOrList<ActionGlob>")]
+    pub action: Option<OneOrList<ActionGlob>>,
+    /// The actions to which this statement does not apply.
+    #[serde(default)]
+    #[schemars(with = "OneOrList<ActionGlob>")]
+    pub not_action: Option<OneOrList<ActionGlob>>,
+    /// The resources to which this statement applies.
+    #[serde(default)]
+    #[schemars(with = "OneOrList<ArnPattern>")]
+    pub resource: Option<OneOrList<ArnPattern>>,
+    /// The resources to which this statement does not apply.
+    #[serde(default)]
+    #[schemars(with = "OneOrList<ArnPattern>")]
+    pub not_resource: Option<OneOrList<ArnPattern>>,
+    /// Conditions on the request context that must all be satisfied for the
+    /// statement to apply.
+    #[serde(default)]
+    pub condition: Condition,
+}
+
+impl TryFrom<StatementDocument> for Statement {
+    type Error = String;
 
-    #[serde(deserialize_with = "de_string_or_list")]
-    pub action: Vec<String>, // TODO: Or NotAction
+    fn try_from(document: StatementDocument) -> Result<Statement, String> {
+        Ok(Statement {
+            sid: document.sid,
+            principal: PrincipalOrNot::from_elements(document.principal, document.not_principal)?,
+            effect: document.effect,
+            resource: Some(ResourceOrNotResource::from_elements(
+                document.resource,
+                document.not_resource,
+            )?),
+            action: ActionOrNotAction::from_elements(document.action, document.not_action)?,
+            condition: document.condition,
+            expands_variables: false,
+        })
+    }
+}
+
+/// Either an `Action` or `NotAction` element: a statement must have exactly one.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub enum ActionOrNotAction {
+    /// The statement applies to actions matching any of these globs.
+    Action(Vec<ActionGlob>),
+    /// The statement applies to all actions except those matching any of these globs.
+    NotAction(Vec<ActionGlob>),
+}
 
-                             // TODO: Conditions
+/// Either a `Resource` or `NotResource` element: a statement must have exactly one.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub enum ResourceOrNotResource {
+    /// The statement applies to resources matching any of these ARN patterns.
+    Resource(Vec<ArnPattern>),
+    /// The statement applies to all resources except those matching any of these
+    /// ARN patterns.
+    NotResource(Vec<ArnPattern>),
 }
 
 impl Statement {
+    /// True if this statement applies to the request.
     pub fn matches(&self, request: &Request) -> bool {
-        // TODO: More conditions: principal, resource, action, conditions, etc.
-
-        for statement_action in &self.action {
-            match ActionGlob::from_str(statement_action) {
-                Ok(glob) => {
-                    if glob.matches(&request.action) {
-                        debug!(?request, ?self, "action matches");
-                        return true;
-                    }
+        let expand_variables = self.expands_variables;
+        self.matches_principal(request)
+            && self.matches_action(request)
+            && self.matches_resource(request, expand_variables)
+            && self.matches_condition(request, expand_variables)
+    }
+
+    /// Statements without a `Principal` or `NotPrincipal`, as in identity policies,
+    /// apply to any principal.
+    fn matches_principal(&self, request: &Request) -> bool {
+        match &self.principal {
+            None => true,
+            Some(principal) => {
+                let matched = principal.matches(&request.principal);
+                if matched {
+                    debug!(?request, ?self, "principal matches");
                 }
-                Err(e) => {
-                    warn!(?statement_action, ?e, "action glob parse error");
+                matched
+            }
+        }
+    }
+
+    fn matches_action(&self, request: &Request) -> bool {
+        match &self.action {
+            ActionOrNotAction::Action(actions) => {
+                let matched = any_action_matches(actions, &request.action);
+                if matched {
+                    debug!(?request, ?self, "action matches");
                 }
+                matched
+            }
+            ActionOrNotAction::NotAction(not_actions) => {
+                let matched = !any_action_matches(not_actions, &request.action);
+                if matched {
+                    debug!(?request, ?self, "action is not excluded by NotAction");
+                }
+                matched
             }
         }
-        false
     }
 
+    fn matches_resource(&self, request: &Request, expand_variables: bool) -> bool {
+        let Some(resource) = &self.resource else {
+            return true;
+        };
+        let (ResourceOrNotResource::Resource(resources)
+        | ResourceOrNotResource::NotResource(resources)) = resource;
+        let Some(patterns) = expand_resources(resources, &request.context, expand_variables) else {
+            debug!(
+                ?request,
+                ?self,
+                "resource has an unresolvable policy variable"
+            );
+            return false;
+        };
+        match resource {
+            ResourceOrNotResource::Resource(_) => {
+                let matched = any_resource_matches(&patterns, &request.resource);
+                if matched {
+                    debug!(?request, ?self, "resource matches");
+                }
+                matched
+            }
+            ResourceOrNotResource::NotResource(_) => {
+                let matched = !any_resource_matches(&patterns, &request.resource);
+                if matched {
+                    debug!(?request, ?self, "resource is not excluded by NotResource");
+                }
+                matched
+            }
+        }
+    }
+
+    fn matches_condition(&self, request: &Request, expand_variables: bool) -> bool {
+        let matched = self
+            .condition
+            .matches_with(&request.context, expand_variables);
+        if matched && !self.condition.is_empty() {
+            debug!(?request, ?self, "condition matches");
+        }
+        matched
+    }
+
+    /// Explain whether each element of this statement matches the request, as for
+    /// [Statement::matches], evaluating every element rather than stopping at the
+    /// first that doesn't match.
+    pub fn trace(&self, index: usize, request: &Request) -> StatementTrace {
+        let expand_variables = self.expands_variables;
+        let mut elements = Vec::new();
+        if let Some(principal) = &self.principal {
+            elements.push(trace_principal(principal, &request.principal));
+        }
+        elements.push(trace_action(&self.action, &request.action));
+        if let Some(resource) = &self.resource {
+            elements.push(trace_resource(resource, request, expand_variables));
+        }
+        elements.extend(self.condition.trace(&request.context, expand_variables));
+        StatementTrace {
+            statement_index: index,
+            sid: self.sid.clone(),
+            effect: self.effect,
+            matched: elements.iter().all(|element| element.matched),
+            elements,
+        }
+    }
+
+    /// True if this is an `Allow` statement that applies to the request.
     pub fn allows(&self, request: &Request) -> bool {
         self.effect.is_allow() && self.matches(request)
     }
 
+    /// True if this is a `Deny` statement that applies to the request.
     pub fn denies(&self, request: &Request) -> bool {
         self.effect.is_deny() && self.matches(request)
     }
 }
 
+fn any_action_matches(action_globs: &[ActionGlob], action: &str) -> bool {
+    action_globs.iter().any(|glob| glob.matches(action))
+}
+
+fn strings<T: fmt::Display>(items: &[T]) -> Vec<String> {
+    items.iter().map(ToString::to_string).collect()
+}
+
+fn trace_principal(principal_or_not: &PrincipalOrNot, principal: &Principal) -> ElementTrace {
+    let principal_name = match principal {
+        Principal::ARN(arn) => arn,
+        Principal::Service(service) => service,
+    };
+    let matched = principal_or_not.matches(principal);
+    let explanation = match (principal_or_not, matched) {
+        (PrincipalOrNot::Principal(_), true) => format!("{principal_name:?} matches Principal"),
+        (PrincipalOrNot::Principal(_), false) => {
+            format!("{principal_name:?} does not match Principal")
+        }
+        (PrincipalOrNot::NotPrincipal(_), true) => {
+            format!("{principal_name:?} is not excluded by NotPrincipal")
+        }
+        (PrincipalOrNot::NotPrincipal(_), false) => {
+            format!("{principal_name:?} is excluded by NotPrincipal")
+        }
+    };
+    ElementTrace::new(Element::Principal, matched, explanation)
+}
+
+fn trace_action(action_or_not: &ActionOrNotAction, action: &str) -> ElementTrace {
+    let (ActionOrNotAction::Action(globs) | ActionOrNotAction::NotAction(globs)) = action_or_not;
+    let matching_glob = globs.iter().find(|glob| glob.matches(action));
+    let globs = strings(globs);
+    let (matched, explanation) = match (action_or_not, matching_glob) {
+        (ActionOrNotAction::Action(_), Some(glob)) => {
+            (true, format!("{action:?} matches {:?}", glob.as_str()))
+        }
+        (ActionOrNotAction::Action(_), None) => {
+            (false, format!("{action:?} does not match {globs:?}"))
+        }
+        (ActionOrNotAction::NotAction(_), Some(glob)) => (
+            false,
+            format!("{action:?} is excluded by NotAction {:?}", glob.as_str()),
+        ),
+        (ActionOrNotAction::NotAction(_), None) => (
+            true,
+            format!("{action:?} is not excluded by NotAction {globs:?}"),
+        ),
+    };
+    ElementTrace::new(Element::Action, matched, explanation)
+}
+
+fn trace_resource(
+    resource_or_not: &ResourceOrNotResource,
+    request: &Request,
+    expand_variables: bool,
+) -> ElementTrace {
+    let (ResourceOrNotResource::Resource(resources)
+    | ResourceOrNotResource::NotResource(resources)) = resource_or_not;
+    let Some(patterns) = expand_resources(resources, &request.context, expand_variables) else {
+        return ElementTrace::new(
+            Element::Resource,
+            false,
+            format!(
+                "{:?} has an unresolvable policy variable",
+                strings(resources)
+            ),
+        );
+    };
+    let resource = &request.resource;
+    let matching_pattern = patterns
+        .iter()
+        .find(|pattern| arn_pattern_matches(pattern, resource));
+    let patterns = patterns
+        .iter()
+        .map(|pattern| pattern.to_string())
+        .collect::<Vec<_>>();
+    let (matched, explanation) = match (resource_or_not, matching_pattern) {
+        (ResourceOrNotResource::Resource(_), Some(pattern)) => (
+            true,
+            format!("{resource:?} matches {:?}", pattern.to_string()),
+        ),
+        (ResourceOrNotResource::Resource(_), None) => {
+            (false, format!("{resource:?} does not match {patterns:?}"))
+        }
+        (ResourceOrNotResource::NotResource(_), Some(pattern)) => (
+            false,
+            format!(
+                "{resource:?} is excluded by NotResource {:?}",
+                pattern.to_string()
+            ),
+        ),
+        (ResourceOrNotResource::NotResource(_), None) => (
+            true,
+            format!("{resource:?} is not excluded by NotResource {patterns:?}"),
+        ),
+    };
+    ElementTrace::new(Element::Resource, matched, explanation)
+}
+
+/// Expand policy variables in each of several resource patterns.
+///
+/// Returns None if any variable can't be resolved.
+fn expand_resources<'a>(
+    patterns: &'a [ArnPattern],
+    context: &Context,
+    expand_variables: bool,
+) -> Option<Vec<Cow<'a, Pattern>>> {
+    patterns
+        .iter()
+        .map(|pattern| pattern.expand(context, expand_variables))
+        .collect()
+}
+
+fn any_resource_matches(resource_patterns: &[Cow<Pattern>], resource: &str) -> bool {
+    resource_patterns
+        .iter()
+        .any(|pattern| arn_pattern_matches(pattern, resource))
+}
+
+impl ActionOrNotAction {
+    /// The element from whichever of `Action` or `NotAction` is in a statement.
+    pub(crate) fn from_elements(
+        action: Option<OneOrList<ActionGlob>>,
+        not_action: Option<OneOrList<ActionGlob>>,
+    ) -> Result<ActionOrNotAction, String> {
+        match exactly_one_of(["Action", "NotAction"], [action, not_action])? {
+            (0, actions) => Ok(ActionOrNotAction::Action(actions.0)),
+            (_, not_actions) => Ok(ActionOrNotAction::NotAction(not_actions.0)),
+        }
+    }
+}
+
+impl ResourceOrNotResource {
+    /// The element from whichever of `Resource` or `NotResource` is in a statement.
+    pub(crate) fn from_elements(
+        resource: Option<OneOrList<ArnPattern>>,
+        not_resource: Option<OneOrList<ArnPattern>>,
+    ) -> Result<ResourceOrNotResource, String> {
+        match exactly_one_of(["Resource", "NotResource"], [resource, not_resource])? {
+            (0, resources) => Ok(ResourceOrNotResource::Resource(resources.0)),
+            (_, not_resources) => Ok(ResourceOrNotResource::NotResource(not_resources.0)),
+        }
+    }
+}
+
+impl JsonSchema for Statement {
+    fn schema_name() -> String {
+        "Statement".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        statement_schema(
+            gen,
+            "One statement in a policy, stating that requests matching some conditions \
+            should be either allowed or denied.",
+            true,
+        )
+    }
+}
+
+/// The schema of a statement, with or without the `Resource` and `NotResource`
+/// elements.
+///
+/// Each pair of mutually exclusive elements, such as `Action` and `NotAction`, is
+/// two properties of the statement, with a rule that at most one of them, or for a
+/// required element exactly one, is present. JSON Schema can't repeat a keyword
+/// within one object, so the rules are each written as `if`/`then`/`else` and
+/// collected in an `allOf`.
+pub(crate) fn statement_schema(
+    gen: &mut SchemaGenerator,
+    description: &str,
+    has_resource: bool,
+) -> Schema {
+    let mut schema = StatementDocument::json_schema(gen).into_object();
+    schema.metadata().description = Some(description.to_owned());
+    let mut pairs = vec![
+        (["Principal", "NotPrincipal"], false),
+        (["Action", "NotAction"], true),
+    ];
+    if has_resource {
+        pairs.push((["Resource", "NotResource"], true));
+    } else {
+        let properties = &mut schema.object().properties;
+        properties.remove("Resource");
+        properties.remove("NotResource");
+    }
+    schema.subschemas().all_of = Some(
+        pairs
+            .into_iter()
+            .map(|([element, not_element], required)| {
+                let mut rule = json!({
+                    "if": { "required": [element] },
+                    "then": { "not": { "required": [not_element] } },
+                });
+                if required {
+                    rule["else"] = json!({ "required": [not_element] });
+                }
+                serde_json::from_value(rule).expect("valid schema")
+            })
+            .collect(),
+    );
+    Schema::Object(schema)
+}
+
+/// A session policy passed when assuming a role, which limits the permissions of
+/// the session.
+///
+/// In JSON this is either a string, the ARN of a managed policy, or an inline policy
+/// document.
+///
+/// See <https://docs.aws.amazon.com/IAM/latest/UserGuide/access_policies.html#policies_session>.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+pub enum SessionPolicy {
+    /// The ARN of a managed policy, e.g. `arn:aws:iam::111122223333:policy/ReadOnly`.
+    Arn(String),
+    /// A policy document given inline.
+    Inline(Policy),
+}
+
 // See <https://docs.aws.amazon.com/IAM/latest/UserGuide/access_policies.html>
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, JsonSchema)]
 pub enum PolicyType {
     Resource,
     Identity,
     PermissionsBoundary,
     ServiceControl,
+    ResourceControl,
     Session,
 }
+
+/// The type of policy in lower case, e.g. "permissions boundary".
+impl fmt::Display for PolicyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PolicyType::Resource => "resource",
+            PolicyType::Identity => "identity",
+            PolicyType::PermissionsBoundary => "permissions boundary",
+            PolicyType::ServiceControl => "service control",
+            PolicyType::ResourceControl => "resource control",
+            PolicyType::Session => "session",
+        })
+    }
+}
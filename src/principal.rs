@@ -2,34 +2,362 @@
 
 //! IAM principals
 
+use std::fmt;
+use std::str::FromStr;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::de::{self, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+
+use crate::arn::Arn;
+use crate::json::{at_most_one_of, StringOrList};
 
 /// One AWS principal.
 ///
 /// For example this is the caller identity for a request.
 // TODO: More options for federated, canonical user, etc?
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "PascalCase")]
 pub enum Principal {
     /// A user, account, role, etc, identified by an ARN.
     ARN(String),
+    /// An AWS service acting on its own behalf, e.g. "cloudtrail.amazonaws.com".
+    Service(String),
+}
+
+impl Principal {
+    /// The ARN of this principal, or None for a service principal.
+    pub fn arn(&self) -> Option<&str> {
+        match self {
+            Principal::ARN(arn) => Some(arn),
+            Principal::Service(_) => None,
+        }
+    }
+
+    /// If this principal is a role session, such as
+    /// `arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234`, return its parts.
+    pub fn assumed_role(&self) -> Option<AssumedRole> {
+        let arn = Arn::from_str(self.arn()?).ok()?;
+        if arn.service != "sts" {
+            return None;
+        }
+        let (role_name, session_name) = arn
+            .resource
+            .strip_prefix("assumed-role/")?
+            .split_once('/')?;
+        Some(AssumedRole {
+            partition: arn.partition,
+            account_id: arn.account_id,
+            role_name: role_name.to_owned(),
+            session_name: session_name.to_owned(),
+        })
+    }
 }
 
-/// Matches a principal, or a list of principals, or states that they do not match.
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+/// The parts of an assumed-role session ARN,
+/// `arn:aws:sts::{account_id}:assumed-role/{role_name}/{session_name}`.
+///
+/// Note that the role's path is not part of the session ARN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssumedRole {
+    pub partition: String,
+    pub account_id: String,
+    pub role_name: String,
+    pub session_name: String,
+}
+
+impl AssumedRole {
+    /// The session ARN.
+    pub fn arn(&self) -> String {
+        format!(
+            "arn:{}:sts::{}:assumed-role/{}/{}",
+            self.partition, self.account_id, self.role_name, self.session_name
+        )
+    }
+}
+
+/// A `Principal` or `NotPrincipal` element in a statement.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "PascalCase")]
 pub enum PrincipalOrNot {
-    Principal(Vec<PrincipalMapEntry>),
-    NotPrincipal(Vec<String>), // TODO: Also a PrincipalMapEntry?
+    /// The statement applies only to these principals.
+    Principal(Principals),
+    /// The statement applies to every principal except these.
+    NotPrincipal(Principals),
+}
+
+/// The value of a `Principal` or `NotPrincipal` element: either `"*"`, or a map from
+/// the type of principal to one or more principals of that type, such as
+/// `{"AWS": "arn:aws:iam::111122223333:root", "Service": ["ec2.amazonaws.com"]}`.
+///
+/// The map entries are kept in the order they were written, and each value remembers
+/// whether it was a single string or a list, so that policies serialize back in the
+/// same form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principals {
+    /// `"*"`: every principal, including anonymous callers.
+    Star,
+    /// Principals grouped by type.
+    Map(Vec<PrincipalMapEntry>),
 }
 
 /// Some principals, all of the same type, e.g. a list of AWS account ids.
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
-#[serde(rename_all = "PascalCase")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PrincipalMapEntry {
-    AWS(Vec<String>),
-    Federated(Vec<String>),
-    CanonicalUser(Vec<String>),
-    Service(Vec<String>),
+    AWS(StringOrList),
+    Federated(StringOrList),
+    CanonicalUser(StringOrList),
+    Service(StringOrList),
+}
+
+impl PrincipalOrNot {
+    /// The element from whichever of `Principal` or `NotPrincipal` is in a statement,
+    /// if either is.
+    pub(crate) fn from_elements(
+        principal: Option<Principals>,
+        not_principal: Option<Principals>,
+    ) -> Result<Option<PrincipalOrNot>, String> {
+        Ok(
+            match at_most_one_of(["Principal", "NotPrincipal"], [principal, not_principal])? {
+                Some((0, principals)) => Some(PrincipalOrNot::Principal(principals)),
+                Some((_, principals)) => Some(PrincipalOrNot::NotPrincipal(principals)),
+                None => None,
+            },
+        )
+    }
+
+    /// True if a statement with this `Principal` or `NotPrincipal` element applies
+    /// to requests from the given principal.
+    pub fn matches(&self, principal: &Principal) -> bool {
+        match self {
+            PrincipalOrNot::Principal(principals) => principals.matches(principal),
+            PrincipalOrNot::NotPrincipal(principals) => !principals.matches(principal),
+        }
+    }
+
+    /// True if this is a `Principal` element that names the given principal by its
+    /// exact ARN, rather than matching it by its account, its role, or a wildcard.
+    pub fn names(&self, principal: &Principal) -> bool {
+        let (PrincipalOrNot::Principal(Principals::Map(entries)), Some(arn)) =
+            (self, principal.arn())
+        else {
+            return false;
+        };
+        entries.iter().any(|entry| match entry {
+            PrincipalMapEntry::AWS(values) => values.as_slice().iter().any(|v| v == arn),
+            _ => false,
+        })
+    }
+}
+
+impl Principals {
+    /// True if the given principal is one of these principals.
+    pub fn matches(&self, principal: &Principal) -> bool {
+        match self {
+            Principals::Star => true,
+            Principals::Map(entries) => entries.iter().any(|entry| entry.matches(principal)),
+        }
+    }
+}
+
+impl PrincipalMapEntry {
+    /// The principal type, as used for the key in a `Principal` map.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PrincipalMapEntry::AWS(_) => "AWS",
+            PrincipalMapEntry::Federated(_) => "Federated",
+            PrincipalMapEntry::CanonicalUser(_) => "CanonicalUser",
+            PrincipalMapEntry::Service(_) => "Service",
+        }
+    }
+
+    /// The principals in this entry.
+    pub fn values(&self) -> &StringOrList {
+        match self {
+            PrincipalMapEntry::AWS(values)
+            | PrincipalMapEntry::Federated(values)
+            | PrincipalMapEntry::CanonicalUser(values)
+            | PrincipalMapEntry::Service(values) => values,
+        }
+    }
+
+    /// True if any of the principals in this entry match the given principal.
+    ///
+    /// Canonical user ids never match, because requests are always made by an
+    /// ARN or service principal.
+    pub fn matches(&self, principal: &Principal) -> bool {
+        match self {
+            PrincipalMapEntry::AWS(aws_principals) => aws_principals
+                .as_slice()
+                .iter()
+                .any(|aws_principal| aws_principal_matches(aws_principal, principal)),
+            PrincipalMapEntry::Service(services) => match principal {
+                Principal::Service(service) => services.as_slice().contains(service),
+                Principal::ARN(_) => false,
+            },
+            PrincipalMapEntry::Federated(providers) => principal
+                .arn()
+                .is_some_and(|arn| providers.as_slice().iter().any(|p| p == arn)),
+            PrincipalMapEntry::CanonicalUser(_) => false,
+        }
+    }
+}
+
+impl Serialize for Principals {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Principals::Star => serializer.serialize_str("*"),
+            Principals::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for entry in entries {
+                    map.serialize_entry(entry.type_name(), entry.values())?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Principals {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PrincipalsVisitor;
+        impl<'de> Visitor<'de> for PrincipalsVisitor {
+            type Value = Principals;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("\"*\" or a map from principal type to principals")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                if value == "*" {
+                    Ok(Principals::Star)
+                } else {
+                    Err(de::Error::invalid_value(de::Unexpected::Str(value), &self))
+                }
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::MapAccess<'de>,
+            {
+                let mut entries: Vec<PrincipalMapEntry> = Vec::new();
+                while let Some(key) = map.next_key::<String>()? {
+                    let values: StringOrList = map.next_value()?;
+                    let entry = match key.as_str() {
+                        "AWS" => PrincipalMapEntry::AWS(values),
+                        "Federated" => PrincipalMapEntry::Federated(values),
+                        "CanonicalUser" => PrincipalMapEntry::CanonicalUser(values),
+                        "Service" => PrincipalMapEntry::Service(values),
+                        _ => return Err(de::Error::unknown_field(&key, PRINCIPAL_TYPES)),
+                    };
+                    if entries.iter().any(|e| e.type_name() == key) {
+                        return Err(de::Error::duplicate_field(entry.type_name()));
+                    }
+                    entries.push(entry);
+                }
+                Ok(Principals::Map(entries))
+            }
+        }
+
+        deserializer.deserialize_any(PrincipalsVisitor)
+    }
+}
+
+impl JsonSchema for Principals {
+    fn schema_name() -> String {
+        "Principals".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let string_or_list =
+            serde_json::to_value(gen.subschema_for::<StringOrList>()).expect("serialize schema");
+        let properties = PRINCIPAL_TYPES
+            .iter()
+            .map(|name| (name.to_string(), string_or_list.clone()))
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::from_value(json!({
+            "description": "\"*\" for all principals, or a map from principal type to one or more principals.",
+            "oneOf": [
+                { "type": "string", "enum": ["*"] },
+                {
+                    "type": "object",
+                    "properties": properties,
+                    "additionalProperties": false,
+                },
+            ]
+        }))
+        .expect("valid schema")
+    }
+}
+
+const PRINCIPAL_TYPES: &[&str] = &["AWS", "Federated", "CanonicalUser", "Service"];
+
+/// Test whether a principal matches one value from the `AWS` entry of a `Principal`
+/// element.
+///
+/// * `*` matches every principal.
+/// * An account id such as `111122223333`, or the equivalent
+///   `arn:aws:iam::111122223333:root`, matches every principal in that account.
+/// * A role ARN matches the role itself and all sessions of the role, such as
+///   `arn:aws:sts::111122223333:assumed-role/Role/session`. The role's path is not
+///   part of the session ARN, so only its name is compared.
+/// * Any other ARN, such as a user or a specific role session, must match exactly.
+///
+/// Unlike resources, principal ARNs may not contain wildcards.
+pub fn aws_principal_matches(aws_principal: &str, principal: &Principal) -> bool {
+    if aws_principal == "*" {
+        return true;
+    }
+    let Some(principal_arn) = principal.arn() else {
+        return false;
+    };
+    if aws_principal == principal_arn {
+        return true;
+    }
+    let Ok(arn) = Arn::from_str(principal_arn) else {
+        return false;
+    };
+    if is_account_id(aws_principal) {
+        return arn.account_id == aws_principal;
+    }
+    let Ok(pattern) = Arn::from_str(aws_principal) else {
+        return false;
+    };
+    if pattern.partition != arn.partition
+        || pattern.account_id != arn.account_id
+        || pattern.service != "iam"
+    {
+        return false;
+    }
+    if pattern.resource == "root" {
+        return true;
+    }
+    if let Some(role_path_and_name) = pattern.resource.strip_prefix("role/") {
+        let role_name = role_path_and_name
+            .rsplit('/')
+            .next()
+            .expect("rsplit returns at least one part");
+        return principal
+            .assumed_role()
+            .is_some_and(|session| session.role_name == role_name);
+    }
+    false
+}
+
+/// True if this is a twelve-digit AWS account id.
+pub fn is_account_id(s: &str) -> bool {
+    s.len() == 12 && s.bytes().all(|b| b.is_ascii_digit())
 }
@@ -4,8 +4,10 @@
 //! name and relevant parameters and context.
 
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
+use crate::context::Context;
+use crate::policy::SessionPolicy;
 use crate::principal::Principal;
 
 /// The attributes of an AWS API request relevant to IAM policy evaluation.
@@ -16,5 +18,50 @@ pub struct Request {
     pub action: String,
     /// The principal issuing the request.
     pub principal: Principal,
-    // TODO: Resource, source IP, and other context from <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html#policy-eval-reqcontext>.
+    /// The ARN of the resource acted upon, e.g. "arn:aws:s3:::mybucket/mykey".
+    ///
+    /// For actions that don't apply to any specific resource, such as
+    /// "s3:ListAllMyBuckets", this is "*".
+    #[serde(default = "star")]
+    pub resource: String,
+    /// Condition keys and their values for this request, such as "aws:SourceIp",
+    /// "aws:PrincipalTag/team", or "s3:prefix".
+    ///
+    /// The values of well-known keys are checked when the request is deserialized.
+    #[serde(
+        default,
+        deserialize_with = "de_context",
+        skip_serializing_if = "Context::is_empty"
+    )]
+    pub context: Context,
+    /// Session policies passed when the principal's session was created, which
+    /// limit what its identity policies can allow.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub session_policies: Vec<SessionPolicy>,
+}
+
+impl Request {
+    /// Make a request with an empty context and no session policies.
+    pub fn new(principal: Principal, action: &str, resource: &str) -> Request {
+        Request {
+            action: action.to_owned(),
+            principal,
+            resource: resource.to_owned(),
+            context: Context::new(),
+            session_policies: Vec::new(),
+        }
+    }
+}
+
+fn star() -> String {
+    "*".to_string()
+}
+
+fn de_context<'de, D>(deserializer: D) -> Result<Context, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let context = Context::deserialize(deserializer)?;
+    context.validate().map_err(de::Error::custom)?;
+    Ok(context)
 }
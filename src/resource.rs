@@ -0,0 +1,30 @@
+// Copyright 2023 Martin Pool
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::arn::arn_glob_matches;
+
+/// Resources owned by an account.
+///
+/// This is needed for resources such as S3 buckets whose ARNs don't contain the
+/// owning account, so that cross-account requests can be recognized.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct Resource {
+    /// ARN patterns matching the resources, e.g. `arn:aws:s3:::artifacts` and
+    /// `arn:aws:s3:::artifacts/*` for a bucket and its objects.
+    pub arn: Vec<String>,
+
+    /// The id of the account that owns the resources.
+    pub account_id: String,
+}
+
+impl Resource {
+    /// True if the given ARN is one of these resources.
+    pub fn matches(&self, arn: &str) -> bool {
+        self.arn
+            .iter()
+            .any(|pattern| arn_glob_matches(pattern, arn))
+    }
+}
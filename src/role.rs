@@ -0,0 +1,219 @@
+// Copyright 2023 Martin Pool
+
+use std::collections::HashMap;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::condition::Condition;
+use crate::effect::Effect;
+use crate::policy::{statement_schema, ActionOrNotAction, Policy, Statement, StatementDocument};
+use crate::principal::PrincipalOrNot;
+use crate::tag::Tag;
+use crate::user::slash;
+use crate::variable::expands_variables;
+
+/// An IAM role, which can be assumed by the principals its trust policy allows.
+///
+/// `P` is the type of the inline policies, as for [crate::user::User], and `T` is the
+/// type of the trust policy.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    rename_all = "PascalCase",
+    bound(deserialize = "P: Deserialize<'de>, T: Deserialize<'de>")
+)]
+pub struct Role<P = Policy, T = TrustPolicy> {
+    /// The role's name.
+    pub role_name: String,
+
+    /// The role's id, used as the first part of `aws:userid` in its sessions.
+    #[serde(default)]
+    pub role_id: Option<String>,
+
+    /// The id of the account containing the role, if it's not the scenario's
+    /// `AccountId`.
+    #[serde(default)]
+    pub account_id: Option<String>,
+
+    /// The role's path, e.g. `/service-role/`.
+    #[serde(default = "slash")]
+    pub path: String,
+
+    /// The trust policy, stating which principals may assume the role.
+    pub assume_role_policy_document: T,
+
+    /// Named policies attached to this role.
+    #[serde(default)]
+    pub attached_policies: Vec<String>,
+
+    /// Policies embedded in this role, indexed by name.
+    #[serde(default)]
+    pub inline_policies: HashMap<String, P>,
+
+    /// The maximum duration of sessions of this role, in seconds, between one and
+    /// twelve hours.
+    #[serde(
+        default = "default_max_session_duration",
+        deserialize_with = "de_max_session_duration"
+    )]
+    #[schemars(range(min = 3600, max = 43200))]
+    pub max_session_duration: u32,
+
+    /// The role's tags.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+
+    /// The name of a named policy that sets the maximum permissions that identity
+    /// policies can grant to sessions of this role.
+    #[serde(default)]
+    pub permissions_boundary: Option<String>,
+}
+
+impl<P, T> Role<P, T> {
+    /// The resource part of the role's ARN, e.g. "role/service-role/Deploy".
+    pub fn arn_resource(&self) -> String {
+        format!("role{}{}", self.path, self.role_name)
+    }
+
+    /// The role's ARN, given the account that contains it.
+    pub fn arn(&self, account_id: &str) -> String {
+        format!("arn:aws:iam::{account_id}:{}", self.arn_resource())
+    }
+}
+
+/// The default maximum session duration of one hour.
+fn default_max_session_duration() -> u32 {
+    3600
+}
+
+/// Read a maximum session duration, which must be between one and twelve hours.
+fn de_max_session_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let seconds = u32::deserialize(deserializer)?;
+    if (3600..=43200).contains(&seconds) {
+        Ok(seconds)
+    } else {
+        Err(de::Error::custom(format!(
+            "MaxSessionDuration {seconds} is not between 3600 and 43200 seconds"
+        )))
+    }
+}
+
+/// A role's trust policy, stating which principals may assume the role.
+///
+/// Its statements have no `Resource` or `NotResource`, because they apply only to
+/// the role that holds them. It's evaluated as a [Policy] whose statements have no
+/// resource, and so match any resource.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct TrustPolicy(pub Policy);
+
+/// A role's trust policy, stating which principals may assume the role.
+///
+/// This is the JSON form of a [TrustPolicy].
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+struct TrustPolicyDocument {
+    /// The version of the IAM grammar, "2008-10-17" or "2012-10-17".
+    version: Option<String>,
+    /// A user-supplied id for the policy.
+    id: Option<String>,
+    statement: Vec<TrustStatement>,
+}
+
+/// One statement in a trust policy. Unlike statements in other policies, it has no
+/// `Resource` or `NotResource`.
+#[derive(Deserialize)]
+#[serde(try_from = "StatementDocument")]
+struct TrustStatement {
+    /// Statement id.
+    sid: Option<String>,
+
+    /// The principals that may, or may not, assume the role.
+    principal: Option<PrincipalOrNot>,
+
+    /// The effect of this statement: allow or deny.
+    effect: Effect,
+
+    /// The actions to which this statement applies, or to which it does not apply,
+    /// such as `sts:AssumeRole`.
+    action: ActionOrNotAction,
+
+    /// Conditions on the request context that must all be satisfied for the
+    /// statement to apply.
+    condition: Condition,
+}
+
+impl JsonSchema for TrustStatement {
+    fn schema_name() -> String {
+        "TrustStatement".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        statement_schema(
+            gen,
+            "One statement in a trust policy. Unlike statements in other policies, it has \
+            no `Resource` or `NotResource`.",
+            false,
+        )
+    }
+}
+
+impl TryFrom<StatementDocument> for TrustStatement {
+    type Error = String;
+
+    fn try_from(document: StatementDocument) -> Result<TrustStatement, String> {
+        if document.resource.is_some() {
+            return Err("unknown field `Resource` in a trust policy".to_owned());
+        }
+        if document.not_resource.is_some() {
+            return Err("unknown field `NotResource` in a trust policy".to_owned());
+        }
+        Ok(TrustStatement {
+            sid: document.sid,
+            principal: PrincipalOrNot::from_elements(document.principal, document.not_principal)?,
+            effect: document.effect,
+            action: ActionOrNotAction::from_elements(document.action, document.not_action)?,
+            condition: document.condition,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TrustPolicy {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let document = TrustPolicyDocument::deserialize(deserializer)?;
+        let expands_variables = expands_variables(document.version.as_deref());
+        Ok(TrustPolicy(Policy {
+            version: document.version,
+            id: document.id,
+            statement: document
+                .statement
+                .into_iter()
+                .map(|statement| Statement {
+                    sid: statement.sid,
+                    principal: statement.principal,
+                    effect: statement.effect,
+                    resource: None,
+                    action: statement.action,
+                    condition: statement.condition,
+                    expands_variables,
+                })
+                .collect(),
+        }))
+    }
+}
+
+impl JsonSchema for TrustPolicy {
+    fn schema_name() -> String {
+        "TrustPolicy".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        TrustPolicyDocument::json_schema(gen)
+    }
+}
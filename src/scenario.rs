@@ -4,19 +4,34 @@
 //! scenario yields an allow/deny result per request.
 
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use camino::{Utf8Path, Utf8PathBuf};
-use eyre::WrapErr;
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use tracing::{info, trace};
 
-use crate::effect::Effect;
+use crate::arn::Arn;
+use crate::context::{self, Context};
+use crate::decision::{Decision, MatchedStatement, Outcome};
+use crate::error::{Error, ReferenceKind, Result};
+use crate::group::Group;
 use crate::json::FromJson;
-use crate::policy::Policy;
+use crate::organization::Organization;
+use crate::policy::{Policy, PolicyType, SessionPolicy};
+use crate::principal::Principal;
 use crate::request::Request;
+use crate::resource::Resource;
+use crate::role::{Role, TrustPolicy};
+use crate::trace::{PolicyTrace, Trace};
 use crate::user::User;
-use crate::Result;
+
+/// The name given to session policies passed inline, rather than by ARN.
+pub const INLINE_SESSION_POLICY: &str = "(inline session policy)";
+
+/// The path of service-linked roles, which are not affected by service control policies
+/// or resource control policies.
+pub const SERVICE_LINKED_ROLE_PATH: &str = "/aws-service-role/";
 
 /// A scenario file containing policies (and later, other resources).
 #[derive(Debug, Clone, Deserialize, Serialize, Default, JsonSchema)]
@@ -24,11 +39,54 @@ use crate::Result;
 pub struct Scenario {
     /// User-defined managed (named) policies, indexed by name.
     ///
-    /// The name can be used to attach them to an identity or resource.
+    /// The name can be used to attach them to an identity.
     pub named_policies: HashMap<String, Policy>,
 
+    /// Resource-based policies, such as S3 bucket policies, indexed by name.
+    ///
+    /// Every resource policy is considered for every request: the `Resource` elements
+    /// in their statements determine which resources they apply to. Each statement
+    /// must have a `Principal` or `NotPrincipal`, saying who it applies to.
+    #[serde(default, deserialize_with = "de_resource_policies")]
+    pub resource_policies: HashMap<String, Policy>,
+
+    /// The id of the account containing the scenario's users and roles, except those
+    /// that give their own `AccountId`.
+    ///
+    /// Users and roles whose account is not known match principals of the same name
+    /// in any account.
+    #[serde(default)]
+    pub account_id: Option<String>,
+
     /// Users.
     pub users: Vec<User>,
+
+    /// Groups of users.
+    #[serde(default)]
+    pub groups: Vec<Group>,
+
+    /// Roles.
+    #[serde(default)]
+    pub roles: Vec<Role>,
+
+    /// Service control policies, indexed by name, which can be attached to the
+    /// organization's root, OUs and accounts.
+    #[serde(default)]
+    pub service_control_policies: HashMap<String, Policy>,
+
+    /// Resource control policies, indexed by name, which can be attached to the
+    /// organization's root, OUs and accounts.
+    #[serde(default)]
+    pub resource_control_policies: HashMap<String, Policy>,
+
+    /// The accounts that own resources whose ARNs don't name their account.
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+
+    /// The organization containing the accounts of principals in this scenario, if
+    /// any.
+    #[serde(default)]
+    pub organization: Option<Organization>,
 }
 
 /// A scenario containing a configuration of policies referenced by path.
@@ -39,8 +97,48 @@ pub struct ScenarioWithPaths {
     /// the policy.
     pub named_policy_files: HashMap<String, Utf8PathBuf>,
 
-    /// Users.
-    pub users: Vec<User>,
+    /// Resource-based policies, as a map from name to the relative path containing
+    /// the policy. Each statement must have a `Principal` or `NotPrincipal`.
+    #[serde(default)]
+    pub resource_policy_files: HashMap<String, Utf8PathBuf>,
+
+    /// The id of the account containing the scenario's users and roles, except those
+    /// that give their own `AccountId`.
+    ///
+    /// Users and roles whose account is not known match principals of the same name
+    /// in any account.
+    #[serde(default)]
+    pub account_id: Option<String>,
+
+    /// Users, whose inline policies are given as relative paths.
+    pub users: Vec<User<Utf8PathBuf>>,
+
+    /// Groups of users, whose inline policies are given as relative paths.
+    #[serde(default)]
+    pub groups: Vec<Group<Utf8PathBuf>>,
+
+    /// Roles, whose trust policies and inline policies are given as relative paths.
+    #[serde(default)]
+    pub roles: Vec<Role<Utf8PathBuf, Utf8PathBuf>>,
+
+    /// Service control policies, as a map from name to the relative path containing
+    /// the policy.
+    #[serde(default)]
+    pub service_control_policy_files: HashMap<String, Utf8PathBuf>,
+
+    /// Resource control policies, as a map from name to the relative path containing
+    /// the policy.
+    #[serde(default)]
+    pub resource_control_policy_files: HashMap<String, Utf8PathBuf>,
+
+    /// The accounts that own resources whose ARNs don't name their account.
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+
+    /// The organization containing the accounts of principals in this scenario, if
+    /// any.
+    #[serde(default)]
+    pub organization: Option<Organization>,
 }
 
 impl Scenario {
@@ -55,59 +153,834 @@ impl Scenario {
         let swi = ScenarioWithPaths::from_json_file(path)?;
         info!(?swi);
 
-        let mut resource_policies: HashMap<String, Policy> = HashMap::new();
-        for (name, relpath) in swi.named_policy_files {
-            let path = path.parent().unwrap().join(relpath);
-            info!(?name, ?path, "Load referenced resource policy file");
-            let policy = Policy::from_json_file(&path)
-                .wrap_err_with(|| format!("Load referenced policy from {path:?}"))?;
-            resource_policies.insert(name, policy);
+        Ok(Scenario {
+            named_policies: load_policy_files(path, swi.named_policy_files)?,
+            account_id: swi.account_id,
+            resource_policies: load_policy_files::<ResourcePolicy>(
+                path,
+                swi.resource_policy_files,
+            )?
+            .into_iter()
+            .map(|(name, ResourcePolicy(policy))| (name, policy))
+            .collect(),
+            users: swi
+                .users
+                .into_iter()
+                .map(|user| load_user(path, user))
+                .collect::<Result<Vec<User>>>()?,
+            groups: swi
+                .groups
+                .into_iter()
+                .map(|group| load_group(path, group))
+                .collect::<Result<Vec<Group>>>()?,
+            roles: swi
+                .roles
+                .into_iter()
+                .map(|role| load_role(path, role))
+                .collect::<Result<Vec<Role>>>()?,
+            service_control_policies: load_policy_files(path, swi.service_control_policy_files)?,
+            resource_control_policies: load_policy_files(path, swi.resource_control_policy_files)?,
+            resources: swi.resources,
+            organization: swi.organization,
+        })
+    }
+
+    /// Find the user, if any, identified by the request's principal.
+    pub fn find_user(&self, principal: &Principal) -> Option<&User> {
+        let arn = Arn::from_str(principal.arn()?).ok()?;
+        if arn.service != "iam" {
+            return None;
+        }
+        self.users.iter().find(|user| {
+            user.arn_resource() == arn.resource
+                && self.is_in_account(user.account_id.as_deref(), &arn.account_id)
+        })
+    }
+
+    /// True if a user or role, whose own account is `own_account_id` if that's set,
+    /// is in the account `account_id`.
+    ///
+    /// Identities whose account is not known, either from themselves or from the
+    /// scenario, are treated as being in every account.
+    fn is_in_account(&self, own_account_id: Option<&str>, account_id: &str) -> bool {
+        own_account_id
+            .or(self.account_id.as_deref())
+            .is_none_or(|own_account_id| own_account_id == account_id)
+    }
+
+    /// Find the account that owns a resource: the first of the scenario's
+    /// [Resource]s that matches, or otherwise the account in the resource's ARN.
+    ///
+    /// Returns None if the account is not known.
+    pub fn resource_account<'a>(&'a self, resource: &'a str) -> Option<&'a str> {
+        if let Some(owned) = self.resources.iter().find(|r| r.matches(resource)) {
+            return Some(&owned.account_id);
         }
+        let arn_account = resource.split(':').nth(4)?;
+        (!arn_account.is_empty()).then_some(arn_account)
+    }
 
-        Ok(Scenario {
-            named_policies: resource_policies,
-            users: swi.users,
+    /// True if the request's principal and resource are in different accounts.
+    ///
+    /// Requests are treated as being within one account if either account is not
+    /// known.
+    pub fn is_cross_account(&self, request: &Request) -> bool {
+        let principal_account = request
+            .principal
+            .arn()
+            .and_then(|arn| Arn::from_str(arn).ok())
+            .map(|arn| arn.account_id);
+        match (principal_account, self.resource_account(&request.resource)) {
+            (Some(principal_account), Some(resource_account)) => {
+                principal_account != resource_account
+            }
+            _ => false,
+        }
+    }
+
+    /// Find a role by name, in any account.
+    pub fn find_role(&self, role_name: &str) -> Option<&Role> {
+        self.roles.iter().find(|role| role.role_name == role_name)
+    }
+
+    /// Find a role by name within an account.
+    pub fn find_role_in_account(&self, account_id: &str, role_name: &str) -> Option<&Role> {
+        self.roles.iter().find(|role| {
+            role.role_name == role_name
+                && self.is_in_account(role.account_id.as_deref(), account_id)
         })
     }
 
+    /// Find a role by its ARN, such as `arn:aws:iam::111122223333:role/ci/Deploy`.
+    pub fn find_role_by_arn(&self, arn: &Arn) -> Option<&Role> {
+        if arn.service != "iam" {
+            return None;
+        }
+        self.roles.iter().find(|role| {
+            role.arn_resource() == arn.resource
+                && self.is_in_account(role.account_id.as_deref(), &arn.account_id)
+        })
+    }
+
+    /// Find the role, if any, whose session is the request's principal.
+    pub fn find_role_for_session(&self, principal: &Principal) -> Option<&Role> {
+        let session = principal.assumed_role()?;
+        self.find_role_in_account(&session.account_id, &session.role_name)
+    }
+
+    /// Return the request context, with global condition keys describing the
+    /// principal and the resource's account filled in from the scenario.
+    ///
+    /// Keys already present in the request are not changed.
+    pub fn request_context(&self, request: &Request) -> Context {
+        let mut context = request.context.clone();
+        if let Some(account_id) = self.resource_account(&request.resource) {
+            context.insert_if_absent(context::RESOURCE_ACCOUNT, [account_id]);
+        }
+        let principal_arn = match &request.principal {
+            Principal::ARN(arn) => arn,
+            Principal::Service(service) => {
+                context.insert_if_absent(context::PRINCIPAL_SERVICE_NAME, [service.as_str()]);
+                return context;
+            }
+        };
+        let Ok(arn) = Arn::from_str(principal_arn) else {
+            return context;
+        };
+        // For a role session, aws:PrincipalArn is the ARN of the role, not the session.
+        match request.principal.assumed_role() {
+            Some(session) => {
+                let role_resource = match self.find_role_for_session(&request.principal) {
+                    Some(role) => role.arn_resource(),
+                    None => format!("role/{}", session.role_name),
+                };
+                context.insert_if_absent(
+                    context::PRINCIPAL_ARN,
+                    [format!(
+                        "arn:{}:iam::{}:{role_resource}",
+                        arn.partition, arn.account_id
+                    )],
+                );
+            }
+            None => context.insert_if_absent(context::PRINCIPAL_ARN, [principal_arn]),
+        }
+        context.insert_if_absent(context::PRINCIPAL_ACCOUNT, [arn.account_id.as_str()]);
+        let principal_type = match (arn.service.as_str(), arn.resource.as_str()) {
+            ("iam", "root") => Some("Account"),
+            ("iam", r) if r.starts_with("user/") => Some("User"),
+            ("sts", r) if r.starts_with("assumed-role/") => Some("AssumedRole"),
+            ("sts", r) if r.starts_with("federated-user/") => Some("FederatedUser"),
+            _ => None,
+        };
+        if let Some(principal_type) = principal_type {
+            context.insert_if_absent(context::PRINCIPAL_TYPE, [principal_type]);
+        }
+        if let Some(user) = self.find_user(&request.principal) {
+            context.insert_if_absent(context::USERNAME, [user.user_name.as_str()]);
+            if let Some(user_id) = &user.user_id {
+                context.insert_if_absent(context::USERID, [user_id.as_str()]);
+            }
+            for tag in &user.tags {
+                context.insert_if_absent(
+                    format!("{}{}", context::PRINCIPAL_TAG_PREFIX, tag.key),
+                    [tag.value.as_str()],
+                );
+            }
+        }
+        if let (Some(session), Some(role)) = (
+            request.principal.assumed_role(),
+            self.find_role_for_session(&request.principal),
+        ) {
+            if let Some(role_id) = &role.role_id {
+                context.insert_if_absent(
+                    context::USERID,
+                    [format!("{role_id}:{}", session.session_name)],
+                );
+            }
+            for tag in &role.tags {
+                context.insert_if_absent(
+                    format!("{}{}", context::PRINCIPAL_TAG_PREFIX, tag.key),
+                    [tag.value.as_str()],
+                );
+            }
+        }
+        trace!(?context, "Filled in request context");
+        context
+    }
+
     /// Evaluate a request against the policies and configuration of this
     /// scenario.
-    pub fn eval(&self, request: &Request) -> Result<Effect> {
-        // TODO: This should eventually implement the logic in
-        // <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html>
+    ///
+    /// This follows the policy evaluation logic described in
+    /// <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_evaluation-logic.html>,
+    /// checking in order:
+    ///
+    /// 1. An explicit deny in any applicable policy denies the request.
+    /// 2. Service control policies must allow the request at every level of the
+    ///    organization above the principal's account.
+    /// 3. Resource control policies are implicitly allowed by `RCPFullAWSAccess`, so
+    ///    they can only deny, in step 1.
+    /// 4. Within one account, a resource-based policy or an identity policy must allow
+    ///    the request. For a cross-account request, both the resource-based policy and
+    ///    the identity policy must allow it.
+    /// 5. The principal's permissions boundary, if any, must allow the request, unless
+    ///    within one account a resource-based policy allows it by naming the exact ARN
+    ///    of the user or role session.
+    /// 6. The session policies, if any, must allow the request, unless within one
+    ///    account a resource-based policy allows it by naming the exact ARN of the
+    ///    role session.
+    ///
+    /// A request that fails any step is implicitly denied.
+    ///
+    /// The [Decision] records the step that determined the result, and every statement
+    /// that matched the request.
+    pub fn eval(&self, request: &Request) -> Result<Decision> {
+        let request = &Request {
+            context: self.request_context(request),
+            ..request.clone()
+        };
+        let policies = self.applicable_policies(request)?;
+        let matched_statements = policies
+            .iter()
+            .flat_map(|(policy_type, policy_name, policy)| {
+                policy
+                    .matching_statements(request)
+                    .map(move |(statement_index, statement)| MatchedStatement {
+                        policy_name: policy_name.to_owned(),
+                        policy_type,
+                        statement_index,
+                        sid: statement.sid.clone(),
+                        effect: statement.effect,
+                    })
+            })
+            .collect::<Vec<_>>();
+        let decide = |outcome: Outcome, reason: &str| {
+            trace!(?request, ?outcome, reason);
+            Decision {
+                outcome,
+                reason: reason.to_owned(),
+                matched_statements: matched_statements.clone(),
+            }
+        };
+
+        if matched_statements
+            .iter()
+            .any(|statement| statement.effect.is_deny())
+        {
+            return Ok(decide(Outcome::ExplicitDeny, "Denied by a Deny statement"));
+        }
+        if policies
+            .service_control_policies
+            .iter()
+            .any(|level| !any_allows(level, request))
+        {
+            return Ok(decide(
+                Outcome::ImplicitDeny,
+                "Not allowed by service control policies",
+            ));
+        }
+        let resource_allows = any_allows(&policies.resource, request);
+        let identity_allows = any_allows(&policies.identity, request);
+        let cross_account = self.is_cross_account(request);
+        let named_principal = !cross_account
+            && policies
+                .resource
+                .iter()
+                .any(|(_name, policy)| policy.allows_named_principal(request));
+        let named_session = named_principal && request.principal.assumed_role().is_some();
+        if cross_account {
+            if !resource_allows {
+                return Ok(decide(
+                    Outcome::ImplicitDeny,
+                    "Cross-account request not allowed by any resource policy",
+                ));
+            }
+            if !identity_allows {
+                return Ok(decide(
+                    Outcome::ImplicitDeny,
+                    "Cross-account request not allowed by any identity policy",
+                ));
+            }
+        } else if !resource_allows && !identity_allows {
+            return Ok(decide(
+                Outcome::ImplicitDeny,
+                "Not allowed by any resource or identity policy",
+            ));
+        }
+        if !named_principal
+            && policies
+                .permissions_boundary
+                .is_some_and(|(_name, policy)| !policy.allows(request))
+        {
+            return Ok(decide(
+                Outcome::ImplicitDeny,
+                "Not allowed by the permissions boundary",
+            ));
+        }
+        if !named_session && !policies.session.is_empty() && !any_allows(&policies.session, request)
+        {
+            return Ok(decide(
+                Outcome::ImplicitDeny,
+                "Not allowed by any session policy",
+            ));
+        }
+        if named_principal {
+            Ok(decide(
+                Outcome::Allow,
+                "Allowed by a resource policy naming the principal",
+            ))
+        } else {
+            Ok(decide(Outcome::Allow, "Allowed"))
+        }
+    }
 
-        // TODO: Evaluate relevant identity policies .
+    /// Trace the evaluation of every statement in every policy that applies to a
+    /// request, explaining which elements matched and why.
+    ///
+    /// This is more detailed, and slower, than [Scenario::eval], which only records
+    /// the statements that matched.
+    pub fn trace(&self, request: &Request) -> Result<Trace> {
+        let request = &Request {
+            context: self.request_context(request),
+            ..request.clone()
+        };
+        let policies = self
+            .applicable_policies(request)?
+            .iter()
+            .map(|(policy_type, policy_name, policy)| PolicyTrace {
+                policy_name: policy_name.to_owned(),
+                policy_type,
+                statements: policy.trace(request),
+            })
+            .collect();
+        Ok(Trace { policies })
+    }
+
+    /// Find all the policies of each type that apply to a request.
+    fn applicable_policies<'a>(&'a self, request: &'a Request) -> Result<ApplicablePolicies<'a>> {
+        Ok(ApplicablePolicies {
+            service_control_policies: self.service_control_policies(&request.principal)?,
+            resource_control_policies: self.resource_control_policies(request)?,
+            resource: self.resource_policies_for(&request.resource),
+            identity: self.identity_policies(&request.principal)?,
+            permissions_boundary: self.permissions_boundary(&request.principal)?,
+            session: self.session_policies(request)?,
+        })
+    }
 
-        // First, does any policy deny the request?
-        if self
-            .named_policies
-            .values()
-            .any(|policy| policy.denies(request))
+    /// Find the identity policies that apply to a principal, as pairs of the
+    /// policy name and the policy.
+    ///
+    /// For a user, these are the user's own attached and inline policies, followed by
+    /// those of each group it belongs to. For a role session, they are the role's
+    /// attached and inline policies. Other principals have no identity policies.
+    ///
+    /// It's an error for an identity to reference a policy or group that is not in
+    /// the scenario.
+    pub fn identity_policies(&self, principal: &Principal) -> Result<Vec<(&str, &Policy)>> {
+        if let Some(role) = self.find_role_for_session(principal) {
+            return self.attached_and_inline_policies(
+                &format!("Role {:?}", role.role_name),
+                &role.attached_policies,
+                &role.inline_policies,
+            );
+        }
+        let Some(user) = self.find_user(principal) else {
+            return Ok(Vec::new());
+        };
+        let mut policies = self.attached_and_inline_policies(
+            &format!("User {:?}", user.user_name),
+            &user.attached_policies,
+            &user.inline_policies,
+        )?;
+        for group_name in &user.groups {
+            let group = self.find_group(group_name).ok_or_else(|| {
+                Error::undefined(
+                    Some(format!("User {:?}", user.user_name)),
+                    ReferenceKind::Group,
+                    group_name,
+                )
+            })?;
+            policies.extend(self.attached_and_inline_policies(
+                &format!("Group {group_name:?}"),
+                &group.attached_policies,
+                &group.inline_policies,
+            )?);
+        }
+        Ok(policies)
+    }
+
+    /// Find the permissions boundary, if any, of a user or of the role of a session,
+    /// as a pair of the policy name and the policy.
+    ///
+    /// It's an error for the boundary to name a policy that is not in the scenario.
+    pub fn permissions_boundary(&self, principal: &Principal) -> Result<Option<(&str, &Policy)>> {
+        let (owner, boundary) = if let Some(role) = self.find_role_for_session(principal) {
+            (
+                format!("Role {:?}", role.role_name),
+                &role.permissions_boundary,
+            )
+        } else if let Some(user) = self.find_user(principal) {
+            (
+                format!("User {:?}", user.user_name),
+                &user.permissions_boundary,
+            )
+        } else {
+            return Ok(None);
+        };
+        let Some(name) = boundary else {
+            return Ok(None);
+        };
+        self.named_policies
+            .get_key_value(name)
+            .map(|(name, policy)| Some((name.as_str(), policy)))
+            .ok_or_else(|| Error::undefined(Some(owner), ReferenceKind::PermissionsBoundary, name))
+    }
+
+    /// Resolve the session policies passed with a request, as pairs of the policy name
+    /// and the policy.
+    ///
+    /// Managed policies are named by their ARN, and looked up by the last part of
+    /// the ARN in the scenario's named policies. Inline session policies are named
+    /// [INLINE_SESSION_POLICY].
+    pub fn session_policies<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> Result<Vec<(&'a str, &'a Policy)>> {
+        request
+            .session_policies
+            .iter()
+            .map(|session_policy| self.resolve_session_policy(session_policy))
+            .collect()
+    }
+
+    /// Resolve one session policy, as a pair of its name and the policy.
+    ///
+    /// It's an error for a managed policy ARN not to name a policy in the scenario.
+    pub fn resolve_session_policy<'a>(
+        &'a self,
+        session_policy: &'a SessionPolicy,
+    ) -> Result<(&'a str, &'a Policy)> {
+        match session_policy {
+            SessionPolicy::Inline(policy) => Ok((INLINE_SESSION_POLICY, policy)),
+            SessionPolicy::Arn(policy_arn) => {
+                let arn = Arn::from_str(policy_arn)?;
+                let name = match arn.resource.strip_prefix("policy/") {
+                    Some(path_and_name) if arn.service == "iam" => {
+                        path_and_name.rsplit('/').next().unwrap_or_default()
+                    }
+                    _ => {
+                        return Err(Error::InvalidArn {
+                            arn: policy_arn.to_owned(),
+                            reason: "is not a managed policy ARN".to_owned(),
+                        })
+                    }
+                };
+                let policy = self.named_policies.get(name).ok_or_else(|| {
+                    Error::undefined(None, ReferenceKind::SessionPolicy, policy_arn)
+                })?;
+                Ok((policy_arn.as_str(), policy))
+            }
+        }
+    }
+
+    /// Find the service control policies that apply to a principal, as a list of
+    /// pairs of the policy name and the policy for each level of the organization from
+    /// the root down to the principal's account.
+    ///
+    /// The list is empty if SCPs don't apply to the principal: if there is no
+    /// organization, or the principal is not in one of its accounts, or is in the
+    /// management account, or is a service principal or a service-linked role.
+    ///
+    /// It's an error for the organization to attach a policy that is not in the
+    /// scenario.
+    pub fn service_control_policies(
+        &self,
+        principal: &Principal,
+    ) -> Result<Vec<Vec<(&str, &Policy)>>> {
+        let Some(organization) = &self.organization else {
+            return Ok(Vec::new());
+        };
+        let Some(Ok(arn)) = principal.arn().map(Arn::from_str) else {
+            return Ok(Vec::new());
+        };
+        if organization.is_management_account(&arn.account_id)
+            || self.is_service_linked_role_session(principal)
         {
-            return Ok(Effect::Deny);
-        }
-        // TODO: Check for an Allow in various policy types in succession.
-        // TODO: The interpretation of the resource-based policy depends on the
-        // type of principal in the request.
-        // TODO: Only check relevant resource policies.
-        if self
-            .named_policies
-            .values()
-            .any(|policy| policy.allows(request))
+            return Ok(Vec::new());
+        }
+        let Some(path) = organization.path_to_account(&arn.account_id) else {
+            return Ok(Vec::new());
+        };
+        path.iter()
+            .map(|level| {
+                resolve_organization_policies(
+                    level.name,
+                    ReferenceKind::ServiceControlPolicy,
+                    level.service_control_policies,
+                    &self.service_control_policies,
+                )
+            })
+            .collect()
+    }
+
+    /// Find the resource control policies that apply to the resource of a request,
+    /// as pairs of the policy name and the policy, from the root of the organization
+    /// down to the account that owns the resource.
+    ///
+    /// AWS attaches `RCPFullAWSAccess` to every level, and custom RCPs may only deny,
+    /// so RCPs are only checked for explicit denies.
+    ///
+    /// The resource's account is found by [Scenario::resource_account], so it may come
+    /// from the scenario's [Resource]s for resources such as S3 buckets whose ARNs
+    /// don't name an account.
+    ///
+    /// The list is empty if RCPs don't apply to the request: if there is no
+    /// organization, or the resource's account is not known or is not one of its
+    /// accounts, or is the management account, or the principal is a service-linked
+    /// role.
+    ///
+    /// It's an error for the organization to attach a policy that is not in the
+    /// scenario.
+    pub fn resource_control_policies(&self, request: &Request) -> Result<Vec<(&str, &Policy)>> {
+        let Some(organization) = &self.organization else {
+            return Ok(Vec::new());
+        };
+        let Some(account_id) = self.resource_account(&request.resource) else {
+            return Ok(Vec::new());
+        };
+        if organization.is_management_account(account_id)
+            || self.is_service_linked_role_session(&request.principal)
         {
-            return Ok(Effect::Allow);
+            return Ok(Vec::new());
+        }
+        let Some(path) = organization.path_to_account(account_id) else {
+            return Ok(Vec::new());
+        };
+        let mut policies = Vec::new();
+        for level in path {
+            policies.extend(resolve_organization_policies(
+                level.name,
+                ReferenceKind::ResourceControlPolicy,
+                level.resource_control_policies,
+                &self.resource_control_policies,
+            )?);
         }
+        Ok(policies)
+    }
 
-        trace!(?request, "No policy matched, so implicit deny");
-        Ok(Effect::Deny)
+    /// True if the principal is a session of a service-linked role, which is not
+    /// affected by SCPs or RCPs.
+    fn is_service_linked_role_session(&self, principal: &Principal) -> bool {
+        self.find_role_for_session(principal)
+            .is_some_and(|role| role.path.starts_with(SERVICE_LINKED_ROLE_PATH))
     }
 
-    pub fn add_resource_policy(&mut self, name: &str, policy: Policy) {
+    /// Find the resource-based policies that may apply to a resource, as pairs of the
+    /// policy name and the policy.
+    ///
+    /// These are all the scenario's resource policies, and, if the resource is a role
+    /// in the scenario, its trust policy, named by the role name.
+    pub fn resource_policies_for(&self, resource: &str) -> Vec<(&str, &Policy)> {
+        let mut policies = self
+            .resource_policies
+            .iter()
+            .map(|(name, policy)| (name.as_str(), policy))
+            .collect::<Vec<_>>();
+        policies.sort_by_key(|(name, _)| *name);
+        if let Ok(arn) = Arn::from_str(resource) {
+            if arn.service == "iam" {
+                policies.extend(
+                    self.roles
+                        .iter()
+                        .filter(|role| {
+                            role.arn_resource() == arn.resource
+                                && self.is_in_account(role.account_id.as_deref(), &arn.account_id)
+                        })
+                        .map(|role| (role.role_name.as_str(), &role.assume_role_policy_document.0)),
+                );
+            }
+        }
+        policies
+    }
+
+    /// Find a group by name.
+    pub fn find_group(&self, group_name: &str) -> Option<&Group> {
+        self.groups
+            .iter()
+            .find(|group| group.group_name == group_name)
+    }
+
+    /// Resolve the named policies attached to an identity, followed by its inline
+    /// policies in order by name.
+    ///
+    /// `owner` describes the identity for error messages.
+    fn attached_and_inline_policies<'a>(
+        &'a self,
+        owner: &str,
+        attached_policies: &'a [String],
+        inline_policies: &'a HashMap<String, Policy>,
+    ) -> Result<Vec<(&'a str, &'a Policy)>> {
+        let mut policies = attached_policies
+            .iter()
+            .map(|name| {
+                self.named_policies
+                    .get_key_value(name)
+                    .map(|(name, policy)| (name.as_str(), policy))
+                    .ok_or_else(|| {
+                        Error::undefined(
+                            Some(owner.to_owned()),
+                            ReferenceKind::AttachedPolicy,
+                            name,
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut inline_policies = inline_policies
+            .iter()
+            .map(|(name, policy)| (name.as_str(), policy))
+            .collect::<Vec<_>>();
+        inline_policies.sort_by_key(|(name, _)| *name);
+        policies.extend(inline_policies);
+        Ok(policies)
+    }
+
+    pub fn add_named_policy(&mut self, name: &str, policy: Policy) {
         assert!(
             !self.named_policies.contains_key(name),
-            "Resource policy {} already exists",
+            "Named policy {} already exists",
             name
         );
         self.named_policies.insert(name.to_owned(), policy);
     }
+
+    /// Add a resource policy.
+    ///
+    /// Panics if there's already a resource policy of this name, or if a statement has
+    /// no `Principal` or `NotPrincipal`.
+    pub fn add_resource_policy(&mut self, name: &str, policy: Policy) {
+        assert!(
+            !self.resource_policies.contains_key(name),
+            "Resource policy {} already exists",
+            name
+        );
+        if let Err(err) = ResourcePolicy::try_from(policy.clone()) {
+            panic!("Resource policy {name}: {err}");
+        }
+        self.resource_policies.insert(name.to_owned(), policy);
+    }
+}
+
+/// The policies of each type that apply to a request, as pairs of the policy name and
+/// the policy.
+struct ApplicablePolicies<'a> {
+    /// Service control policies at each level from the organization root to the
+    /// principal's account.
+    service_control_policies: Vec<Vec<(&'a str, &'a Policy)>>,
+    resource_control_policies: Vec<(&'a str, &'a Policy)>,
+    resource: Vec<(&'a str, &'a Policy)>,
+    identity: Vec<(&'a str, &'a Policy)>,
+    permissions_boundary: Option<(&'a str, &'a Policy)>,
+    session: Vec<(&'a str, &'a Policy)>,
+}
+
+impl<'a> ApplicablePolicies<'a> {
+    /// Iterate all the policies, with their types and names.
+    fn iter(&self) -> impl Iterator<Item = (PolicyType, &'a str, &'a Policy)> + '_ {
+        self.service_control_policies
+            .iter()
+            .flat_map(|level| typed(PolicyType::ServiceControl, level))
+            .chain(typed(
+                PolicyType::ResourceControl,
+                &self.resource_control_policies,
+            ))
+            .chain(typed(PolicyType::Resource, &self.resource))
+            .chain(typed(PolicyType::Identity, &self.identity))
+            .chain(
+                self.permissions_boundary
+                    .map(|(name, policy)| (PolicyType::PermissionsBoundary, name, policy)),
+            )
+            .chain(typed(PolicyType::Session, &self.session))
+    }
+}
+
+/// Label named policies with their type.
+fn typed<'s, 'a>(
+    policy_type: PolicyType,
+    policies: &'s [(&'a str, &'a Policy)],
+) -> impl Iterator<Item = (PolicyType, &'a str, &'a Policy)> + 's {
+    policies
+        .iter()
+        .map(move |(name, policy)| (policy_type, *name, *policy))
+}
+
+/// True if any of the policies allow the request.
+fn any_allows(policies: &[(&str, &Policy)], request: &Request) -> bool {
+    policies
+        .iter()
+        .any(|(_name, policy)| policy.allows(request))
+}
+
+/// Look up the names of policies attached to one level of the organization.
+///
+/// `kind` is the type of policy, for errors.
+fn resolve_organization_policies<'a>(
+    level_name: &str,
+    kind: ReferenceKind,
+    names: &'a [String],
+    policies: &'a HashMap<String, Policy>,
+) -> Result<Vec<(&'a str, &'a Policy)>> {
+    names
+        .iter()
+        .map(|name| {
+            policies
+                .get_key_value(name)
+                .map(|(name, policy)| (name.as_str(), policy))
+                .ok_or_else(|| Error::undefined(Some(format!("{level_name:?}")), kind, name))
+        })
+        .collect()
+}
+
+/// A policy that can be attached to a resource, whose statements all have a
+/// `Principal` or `NotPrincipal`.
+///
+/// Without one, a statement in a resource policy would apply to every caller, which
+/// is almost certainly a mistake, such as loading an identity policy as a resource
+/// policy.
+#[derive(Deserialize)]
+#[serde(try_from = "Policy")]
+struct ResourcePolicy(Policy);
+
+impl TryFrom<Policy> for ResourcePolicy {
+    type Error = String;
+
+    fn try_from(policy: Policy) -> std::result::Result<ResourcePolicy, String> {
+        match policy
+            .statement
+            .iter()
+            .position(|statement| statement.principal.is_none())
+        {
+            Some(index) => Err(format!(
+                "statement {index} of a resource policy has no `Principal` or `NotPrincipal`"
+            )),
+            None => Ok(ResourcePolicy(policy)),
+        }
+    }
+}
+
+/// Deserialize the resource policies of a scenario, checking that they say which
+/// principals they apply to.
+fn de_resource_policies<'de, D>(
+    deserializer: D,
+) -> std::result::Result<HashMap<String, Policy>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        HashMap::<String, ResourcePolicy>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, ResourcePolicy(policy))| (name, policy))
+            .collect(),
+    )
+}
+
+/// Load policies from files named relative to the scenario file.
+fn load_policy_files<P: FromJson>(
+    scenario_path: &Utf8Path,
+    policy_files: HashMap<String, Utf8PathBuf>,
+) -> Result<HashMap<String, P>> {
+    let mut policies = HashMap::new();
+    for (name, relpath) in policy_files {
+        let path = scenario_path.parent().unwrap().join(relpath);
+        info!(?name, ?path, "Load referenced policy file");
+        let policy = P::from_json_file(&path)?;
+        policies.insert(name, policy);
+    }
+    Ok(policies)
+}
+
+/// Load the inline policies of a user from files named relative to the scenario file.
+fn load_user(scenario_path: &Utf8Path, user: User<Utf8PathBuf>) -> Result<User> {
+    Ok(User {
+        inline_policies: load_policy_files(scenario_path, user.inline_policies)?,
+        user_name: user.user_name,
+        user_id: user.user_id,
+        account_id: user.account_id,
+        path: user.path,
+        tags: user.tags,
+        attached_policies: user.attached_policies,
+        groups: user.groups,
+        permissions_boundary: user.permissions_boundary,
+    })
+}
+
+/// Load the inline policies of a group from files named relative to the scenario file.
+fn load_group(scenario_path: &Utf8Path, group: Group<Utf8PathBuf>) -> Result<Group> {
+    Ok(Group {
+        inline_policies: load_policy_files(scenario_path, group.inline_policies)?,
+        group_name: group.group_name,
+        path: group.path,
+        attached_policies: group.attached_policies,
+    })
+}
+
+/// Load the trust policy and inline policies of a role from files named relative to
+/// the scenario file.
+fn load_role(scenario_path: &Utf8Path, role: Role<Utf8PathBuf, Utf8PathBuf>) -> Result<Role> {
+    let trust_policy_path = scenario_path
+        .parent()
+        .unwrap()
+        .join(&role.assume_role_policy_document);
+    Ok(Role {
+        assume_role_policy_document: TrustPolicy::from_json_file(&trust_policy_path)?,
+        inline_policies: load_policy_files(scenario_path, role.inline_policies)?,
+        role_name: role.role_name,
+        role_id: role.role_id,
+        account_id: role.account_id,
+        path: role.path,
+        attached_policies: role.attached_policies,
+        max_session_duration: role.max_session_duration,
+        tags: role.tags,
+        permissions_boundary: role.permissions_boundary,
+    })
 }
@@ -10,14 +10,14 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tracing::{info, info_span, warn};
 
-use crate::effect::Effect;
+use crate::decision::{Decision, Outcome};
 use crate::json::FromJson;
 use crate::scenario::Scenario;
 use crate::Request;
 use crate::Result;
 
 /// A test case containing a scenario (policies and resources) and a series of
-/// assertions (requests and expected effects).
+/// assertions (requests and expected outcomes).
 #[derive(Debug, Clone)]
 pub struct TestCase {
     pub comment: Option<String>,
@@ -29,17 +29,43 @@ pub struct TestCase {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AssertionResult {
     Pass,
-    Fail,
+    /// The request did not have the expected outcome, for the reasons in the decision.
+    Fail(Decision),
     Error(String),
 }
 
 /// A single assertion in a test case, containing a request and the expected
-/// effect, and optionally a comment.
+/// outcome, and optionally a comment.
 #[derive(Debug, Clone)]
 pub struct TestCaseAssertion {
     pub comment: Option<String>,
     pub request: Request,
-    pub expected: Effect,
+    pub expected: ExpectedOutcome,
+}
+
+/// The outcome expected by a test assertion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ExpectedOutcome {
+    /// The request is allowed.
+    Allow,
+    /// The request is denied, either explicitly or implicitly.
+    Deny,
+    /// The request is denied by a `Deny` statement.
+    ExplicitDeny,
+    /// The request is denied because nothing allowed it.
+    ImplicitDeny,
+}
+
+impl ExpectedOutcome {
+    /// True if the actual outcome satisfies this expectation.
+    pub fn matches(&self, outcome: Outcome) -> bool {
+        match self {
+            ExpectedOutcome::Allow => outcome == Outcome::Allow,
+            ExpectedOutcome::Deny => outcome != Outcome::Allow,
+            ExpectedOutcome::ExplicitDeny => outcome == Outcome::ExplicitDeny,
+            ExpectedOutcome::ImplicitDeny => outcome == Outcome::ImplicitDeny,
+        }
+    }
 }
 
 impl TestCase {
@@ -90,13 +116,13 @@ impl TestCase {
                             // Flatten to a string to avoid problems seriializing the error type.
                             AssertionResult::Error(err.to_string())
                         }
-                        Ok(actual) if actual == *expected => {
+                        Ok(decision) if expected.matches(decision.outcome) => {
                             info!("Assertion passed");
                             AssertionResult::Pass
                         }
-                        Ok(_) => {
-                            info!("Assertion failed");
-                            AssertionResult::Fail
+                        Ok(decision) => {
+                            info!(%decision, "Assertion failed");
+                            AssertionResult::Fail(decision)
                         }
                     }
                 },
@@ -121,12 +147,12 @@ pub struct TestCaseWithPaths {
     /// A path to a scenario file, relative to the testcase file.
     pub scenario: Utf8PathBuf,
 
-    /// A series of requests and expected effects.
+    /// A series of requests and expected outcomes.
     pub assertions: Vec<AssertionWithRequestPath>,
 }
 
 /// An assertion in a testcase file, referencing a request file and giving the
-/// expected effect.
+/// expected outcome.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub struct AssertionWithRequestPath {
@@ -136,6 +162,7 @@ pub struct AssertionWithRequestPath {
     /// The path of the request file, relative to the testcase file.
     pub request: Utf8PathBuf,
 
-    /// The expected effect.
-    pub expected: Effect,
+    /// The expected outcome: `Allow`, `Deny`, or more specifically `ExplicitDeny`
+    /// or `ImplicitDeny`.
+    pub expected: ExpectedOutcome,
 }
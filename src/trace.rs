@@ -0,0 +1,138 @@
+// Copyright 2023 Martin Pool
+
+//! A trace of how each statement of each applicable policy was evaluated against a
+//! request, explaining which elements matched and why.
+//!
+//! Tracing is opt-in, through [Scenario::trace](crate::scenario::Scenario::trace),
+//! because it evaluates every element of every statement rather than stopping at the
+//! first mismatch.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::effect::Effect;
+use crate::policy::PolicyType;
+
+/// The evaluation of every statement in every policy that applies to a request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct Trace {
+    pub policies: Vec<PolicyTrace>,
+}
+
+/// The evaluation of each statement in one policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyTrace {
+    pub policy_name: String,
+    pub policy_type: PolicyType,
+    pub statements: Vec<StatementTrace>,
+}
+
+/// The evaluation of one statement: it matches the request only if every element
+/// matches.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct StatementTrace {
+    /// The index of the statement within the policy, counting from 0.
+    pub statement_index: usize,
+
+    /// The statement's `Sid`, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+
+    pub effect: Effect,
+
+    /// True if the statement matches the request.
+    pub matched: bool,
+
+    /// The evaluation of each element of the statement, with one entry for each
+    /// key of each condition operator.
+    pub elements: Vec<ElementTrace>,
+}
+
+/// The evaluation of one element of a statement.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ElementTrace {
+    pub element: Element,
+
+    /// True if this element matches the request.
+    pub matched: bool,
+
+    /// Why the element did or didn't match, e.g.
+    /// `StringEquals aws:PrincipalTag/team: request value "ops" does not satisfy ["dev"]`.
+    pub explanation: String,
+}
+
+/// An element of a statement that is compared to the request.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Element {
+    /// `Principal` or `NotPrincipal`.
+    Principal,
+    /// `Action` or `NotAction`.
+    Action,
+    /// `Resource` or `NotResource`.
+    Resource,
+    /// One key of one operator in the `Condition`.
+    Condition,
+}
+
+impl ElementTrace {
+    pub fn new(element: Element, matched: bool, explanation: String) -> ElementTrace {
+        ElementTrace {
+            element,
+            matched,
+            explanation,
+        }
+    }
+}
+
+/// Describe the trace over several indented lines, one per policy, statement and
+/// element.
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for policy in &self.policies {
+            writeln!(f, "{} policy {:?}", policy.policy_type, policy.policy_name)?;
+            for statement in &policy.statements {
+                write!(f, "  statement {}", statement.statement_index)?;
+                if let Some(sid) = &statement.sid {
+                    write!(f, " ({sid:?})")?;
+                }
+                writeln!(
+                    f,
+                    ", {:?}: {}",
+                    statement.effect,
+                    if statement.matched {
+                        "matched"
+                    } else {
+                        "not matched"
+                    }
+                )?;
+                for element in &statement.elements {
+                    writeln!(f, "    {element}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describe the element, e.g. `action "s3:GetObject" matches "s3:*"`.
+impl fmt::Display for ElementTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.element, self.explanation)
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Element::Principal => "principal",
+            Element::Action => "action",
+            Element::Resource => "resource",
+            Element::Condition => "condition",
+        })
+    }
+}
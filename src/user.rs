@@ -1,19 +1,33 @@
 // Copyright 2023 Martin Pool
 
+use std::collections::HashMap;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::policy::Policy;
 use crate::tag::Tag;
 
 /// An IAM user.
-#[derive(Debug, Clone, Deserialize, Serialize, Default, JsonSchema)]
-#[serde(deny_unknown_fields, rename_all = "PascalCase")]
-pub struct User {
+///
+/// `P` is the type of inline policies: either the policies themselves, or
+/// in a [crate::scenario::ScenarioWithPaths] the paths of files containing them.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(
+    deny_unknown_fields,
+    rename_all = "PascalCase",
+    bound(deserialize = "P: Deserialize<'de>")
+)]
+pub struct User<P = Policy> {
     /// The user's name.
     pub user_name: String,
     /// The user's id.
     #[serde(default)]
     pub user_id: Option<String>,
+    /// The id of the account containing the user, if it's not the scenario's
+    /// `AccountId`.
+    #[serde(default)]
+    pub account_id: Option<String>,
     // /// The user's ARN.
     // // TODO: How should we treat this if unset?
     // #[serde(default)]
@@ -30,8 +44,49 @@ pub struct User {
     /// Named policies attached to this user.
     #[serde(default)]
     pub attached_policies: Vec<String>,
+
+    /// Policies embedded in this user, indexed by name.
+    #[serde(default)]
+    pub inline_policies: HashMap<String, P>,
+
+    /// The names of groups this user belongs to.
+    #[serde(default)]
+    pub groups: Vec<String>,
+
+    /// The name of a named policy that sets the maximum permissions that identity
+    /// policies can grant to this user.
+    #[serde(default)]
+    pub permissions_boundary: Option<String>,
+}
+
+impl<P> Default for User<P> {
+    fn default() -> Self {
+        User {
+            user_name: String::new(),
+            user_id: None,
+            account_id: None,
+            path: slash(),
+            tags: Vec::new(),
+            attached_policies: Vec::new(),
+            inline_policies: HashMap::new(),
+            groups: Vec::new(),
+            permissions_boundary: None,
+        }
+    }
+}
+
+impl<P> User<P> {
+    /// The resource part of the user's ARN, e.g. "user/eng/mateo".
+    pub fn arn_resource(&self) -> String {
+        format!("user{}{}", self.path, self.user_name)
+    }
+
+    /// The user's ARN, given the account that contains it.
+    pub fn arn(&self, account_id: &str) -> String {
+        format!("arn:aws:iam::{account_id}:{}", self.arn_resource())
+    }
 }
 
-fn slash() -> String {
+pub(crate) fn slash() -> String {
     "/".to_string()
 }
@@ -0,0 +1,119 @@
+// Copyright 2023 Martin Pool
+
+//! Policy variables such as `${aws:username}` in resources and condition values.
+//!
+//! See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_policies_variables.html>.
+
+use crate::context::Context;
+use crate::glob::{GlobChar, Pattern};
+
+/// The policy language version that introduced policy variables. In policies
+/// with an older or missing version, `${...}` is treated as literal text.
+pub const VARIABLES_VERSION: &str = "2012-10-17";
+
+/// True if policy variables are expanded in a policy with this `Version`.
+pub fn expands_variables(version: Option<&str>) -> bool {
+    version == Some(VARIABLES_VERSION)
+}
+
+/// Expand policy variables in a resource or condition value from a policy,
+/// producing a wildcard pattern.
+///
+/// `${key}` is replaced by the single value of that key in the request context, and
+/// `${key, 'default'}` falls back to the default if the key is not present. The escapes
+/// `${*}`, `${?}` and `${$}` produce literal `*`, `?` and `$` characters. Substituted
+/// text is always literal, so a `*` in a context value is not a wildcard, while `*` and
+/// `?` elsewhere in the text are wildcards as usual.
+///
+/// Returns None if any variable can't be resolved, because its key is missing or
+/// has multiple values and there is no default. A statement containing an unresolvable
+/// variable does not match the request.
+pub fn expand(text: &str, context: &Context) -> Option<Pattern> {
+    let mut chars = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        chars.extend(Pattern::new(&rest[..start]).0);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            // An unterminated variable is just text.
+            chars.extend(Pattern::new(&rest[start..]).0);
+            return Some(Pattern(chars));
+        };
+        match Variable::parse(&after[..end]) {
+            Some(variable) => {
+                let value = variable.resolve(context)?;
+                chars.extend(value.chars().map(GlobChar::Literal));
+            }
+            None => chars.extend(Pattern::new(&rest[start..start + 2 + end + 1]).0),
+        }
+        rest = &after[end + 1..];
+    }
+    chars.extend(Pattern::new(rest).0);
+    Some(Pattern(chars))
+}
+
+/// Expand variables in each of several values, as for [expand], or if `expand_variables`
+/// is false, parse them as plain patterns.
+///
+/// Returns None if any variable can't be resolved.
+pub fn expand_all(
+    values: &[String],
+    context: &Context,
+    expand_variables: bool,
+) -> Option<Vec<Pattern>> {
+    if expand_variables {
+        values.iter().map(|value| expand(value, context)).collect()
+    } else {
+        Some(values.iter().map(|value| Pattern::new(value)).collect())
+    }
+}
+
+/// The contents of one `${...}` reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable<'a> {
+    /// One of the escapes `${*}`, `${?}` or `${$}`.
+    Escape(char),
+    /// A context key, with an optional default value.
+    Key {
+        key: &'a str,
+        default: Option<&'a str>,
+    },
+}
+
+impl<'a> Variable<'a> {
+    /// Parse the text between `${` and `}`, or return None if it is not a valid
+    /// variable reference.
+    fn parse(inner: &'a str) -> Option<Variable<'a>> {
+        let inner = inner.trim();
+        match inner {
+            "*" => return Some(Variable::Escape('*')),
+            "?" => return Some(Variable::Escape('?')),
+            "$" => return Some(Variable::Escape('$')),
+            _ => (),
+        }
+        let (key, default) = match inner.split_once(',') {
+            None => (inner, None),
+            Some((key, default)) => {
+                let default = default
+                    .trim()
+                    .strip_prefix('\'')
+                    .and_then(|d| d.strip_suffix('\''))?;
+                (key.trim(), Some(default))
+            }
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return None;
+        }
+        Some(Variable::Key { key, default })
+    }
+
+    fn resolve(&self, context: &Context) -> Option<String> {
+        match self {
+            Variable::Escape(c) => Some(c.to_string()),
+            Variable::Key { key, default } => match context.get(key) {
+                Some([value]) => Some(value.clone()),
+                _ => default.map(str::to_owned),
+            },
+        }
+    }
+}
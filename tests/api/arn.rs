@@ -0,0 +1,98 @@
+// Copyright 2023 Martin Pool
+
+use std::str::FromStr;
+
+use iamthat::arn::{arn_glob_matches, Arn};
+
+#[test]
+fn parse_arn() {
+    let arn = Arn::from_str("arn:aws:iam::111122223333:user/eng/mateo").unwrap();
+    assert_eq!(arn.partition, "aws");
+    assert_eq!(arn.service, "iam");
+    assert_eq!(arn.region, "");
+    assert_eq!(arn.account_id, "111122223333");
+    assert_eq!(arn.resource, "user/eng/mateo");
+    assert_eq!(arn.to_string(), "arn:aws:iam::111122223333:user/eng/mateo");
+}
+
+#[test]
+fn parse_arn_with_colons_in_resource() {
+    let arn = Arn::from_str("arn:aws:logs:us-east-1:111122223333:log-group:app:*").unwrap();
+    assert_eq!(arn.region, "us-east-1");
+    assert_eq!(arn.resource, "log-group:app:*");
+}
+
+#[test]
+fn invalid_arns() {
+    assert!(Arn::from_str("*").is_err());
+    assert!(Arn::from_str("arn:aws:s3").is_err());
+    assert!(Arn::from_str("nra:aws:s3:::bucket").is_err());
+}
+
+#[test]
+fn star_matches_anything() {
+    assert!(arn_glob_matches("*", "arn:aws:s3:::bucket"));
+    assert!(arn_glob_matches("*", "*"));
+}
+
+#[test]
+fn literal_arn_matches_only_itself() {
+    assert!(arn_glob_matches(
+        "arn:aws:s3:::bucket",
+        "arn:aws:s3:::bucket"
+    ));
+    assert!(!arn_glob_matches(
+        "arn:aws:s3:::bucket",
+        "arn:aws:s3:::bucket2"
+    ));
+    assert!(!arn_glob_matches(
+        "arn:aws:s3:::bucket",
+        "arn:aws:s3:::BUCKET"
+    ));
+}
+
+#[test]
+fn wildcards_match_within_segments() {
+    assert!(arn_glob_matches(
+        "arn:aws:ec2:*:111122223333:instance/*",
+        "arn:aws:ec2:us-west-2:111122223333:instance/i-0123"
+    ));
+    assert!(!arn_glob_matches(
+        "arn:aws:ec2:*:111122223333:instance/*",
+        "arn:aws:ec2:us-west-2:444455556666:instance/i-0123"
+    ));
+    assert!(arn_glob_matches(
+        "arn:aws:s3:::bucket-???",
+        "arn:aws:s3:::bucket-abc"
+    ));
+    assert!(!arn_glob_matches(
+        "arn:aws:s3:::bucket-???",
+        "arn:aws:s3:::bucket-abcd"
+    ));
+}
+
+#[test]
+fn wildcard_does_not_span_segments() {
+    // The star is in the region segment and can't also swallow the account.
+    assert!(!arn_glob_matches(
+        "arn:aws:ec2:*:instance/*",
+        "arn:aws:ec2:us-west-2:111122223333:instance/i-0123"
+    ));
+}
+
+#[test]
+fn wildcard_in_resource_segment_spans_colons_and_slashes() {
+    assert!(arn_glob_matches(
+        "arn:aws:logs:us-east-1:111122223333:log-group:*",
+        "arn:aws:logs:us-east-1:111122223333:log-group:app:log-stream:x"
+    ));
+    assert!(arn_glob_matches(
+        "arn:aws:s3:::bucket/*",
+        "arn:aws:s3:::bucket/a/b/c"
+    ));
+}
+
+#[test]
+fn non_arn_request_resource_only_matches_star() {
+    assert!(!arn_glob_matches("arn:aws:s3:::*", "*"));
+}
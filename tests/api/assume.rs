@@ -0,0 +1,495 @@
+// Copyright 2023 Martin Pool
+
+use serde_json::json;
+
+use iamthat::assume::{AssumeRoleRequest, Session};
+use iamthat::json::FromJson;
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::tag::Tag;
+use iamthat::Error;
+
+const MATEO: &str = "arn:aws:iam::111122223333:user/mateo";
+const ZHANG: &str = "arn:aws:iam::111122223333:user/zhang";
+
+fn user(arn: &str) -> Session {
+    Session::new(Principal::ARN(arn.to_owned()))
+}
+
+fn tag(key: &str, value: &str) -> Tag {
+    Tag {
+        key: key.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+/// Mateo and Zhang may both try to assume any role, but only Mateo is trusted by
+/// Deploy, and only Deploy is trusted by Release.
+fn scenario_with_role_chain() -> Scenario {
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "AssumeAnyRole": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": ["sts:AssumeRole", "sts:TagSession"],
+                        "Resource": "arn:aws:iam::111122223333:role/*"
+                    }
+                ]
+            },
+            "PutArtifacts": {
+                "Version": "2012-10-17",
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:PutObject",
+                        "Resource": "arn:aws:s3:::artifacts/${aws:PrincipalTag/project}/*"
+                    }
+                ]
+            }
+        },
+        "Users": [
+            { "UserName": "mateo", "AttachedPolicies": ["AssumeAnyRole"] },
+            { "UserName": "zhang", "AttachedPolicies": ["AssumeAnyRole"] },
+            { "UserName": "ana" }
+        ],
+        "Roles": [
+            {
+                "RoleName": "Deploy",
+                "AssumeRolePolicyDocument": {
+                    "Statement": [
+                        {
+                            "Effect": "Allow",
+                            "Principal": { "AWS": [MATEO, "arn:aws:iam::111122223333:user/ana"] },
+                            "Action": ["sts:AssumeRole", "sts:TagSession"]
+                        }
+                    ]
+                },
+                "AttachedPolicies": ["AssumeAnyRole", "PutArtifacts"],
+                "MaxSessionDuration": 7200
+            },
+            {
+                "RoleName": "Release",
+                "AssumeRolePolicyDocument": {
+                    "Statement": [
+                        {
+                            "Effect": "Allow",
+                            "Principal": { "AWS": "arn:aws:iam::111122223333:role/Deploy" },
+                            "Action": ["sts:AssumeRole", "sts:TagSession"]
+                        },
+                        {
+                            "Effect": "Deny",
+                            "Principal": "*",
+                            "Action": "sts:AssumeRole",
+                            "Condition": {
+                                "StringEquals": { "sts:RoleSessionName": "forbidden" }
+                            }
+                        }
+                    ]
+                },
+                "AttachedPolicies": ["PutArtifacts"]
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+#[test]
+fn assume_trusted_role() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(user(MATEO), &[AssumeRoleRequest::new("Deploy")])
+        .unwrap();
+    assert!(chain.is_allowed());
+    let hop = &chain.hops[0];
+    assert_eq!(hop.role_arn, "arn:aws:iam::111122223333:role/Deploy");
+    assert!(hop.identity_allows);
+    assert!(hop.trust_allows);
+    assert_eq!(hop.tag_session_allows, None);
+    assert_eq!(
+        chain.session.unwrap().principal,
+        Principal::ARN("arn:aws:sts::111122223333:assumed-role/Deploy/iamthat".to_owned())
+    );
+}
+
+#[test]
+fn role_chain_with_transitive_tags() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest {
+                    role_session_name: Some("build-17".to_owned()),
+                    tags: vec![tag("project", "widget"), tag("ticket", "1234")],
+                    transitive_tag_keys: vec!["project".to_owned()],
+                    ..AssumeRoleRequest::new("Deploy")
+                },
+                AssumeRoleRequest::new("Release"),
+            ],
+        )
+        .unwrap();
+    assert!(chain.is_allowed(), "{chain:#?}");
+    assert_eq!(chain.hops.len(), 2);
+    assert_eq!(chain.hops[0].tag_session_allows, Some(true));
+    assert_eq!(
+        chain.hops[1].caller,
+        Principal::ARN("arn:aws:sts::111122223333:assumed-role/Deploy/build-17".to_owned())
+    );
+    let session = chain.session.unwrap();
+    assert_eq!(
+        session.principal,
+        Principal::ARN("arn:aws:sts::111122223333:assumed-role/Release/iamthat".to_owned())
+    );
+    // Only the transitive tag is carried into the second session.
+    assert_eq!(session.tags.len(), 1);
+    assert_eq!(session.tags[0].key, "project");
+    assert_eq!(session.transitive_tag_keys, ["project"]);
+
+    // The final session's tags are available to its policies.
+    let request = session.request("s3:PutObject", "arn:aws:s3:::artifacts/widget/app.tar.gz");
+    assert!(scenario.eval(&request).unwrap().is_allow());
+    let request = session.request("s3:PutObject", "arn:aws:s3:::artifacts/gadget/app.tar.gz");
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+#[test]
+fn untrusted_caller_is_denied() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(user(ZHANG), &[AssumeRoleRequest::new("Deploy")])
+        .unwrap();
+    assert!(!chain.is_allowed());
+    let hop = &chain.hops[0];
+    assert!(hop.identity_allows);
+    assert!(!hop.trust_allows);
+    assert!(hop
+        .denied_because
+        .as_ref()
+        .unwrap()
+        .contains("trust policy"));
+}
+
+#[test]
+fn trust_policy_naming_caller_needs_no_identity_policy() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(
+            user("arn:aws:iam::111122223333:user/ana"),
+            &[AssumeRoleRequest::new("Deploy")],
+        )
+        .unwrap();
+    assert!(chain.is_allowed(), "{chain:#?}");
+    assert!(!chain.hops[0].identity_allows);
+    assert!(chain.hops[0].trust_allows);
+}
+
+#[test]
+fn trust_policy_naming_account_needs_identity_policy() {
+    let scenario = Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {},
+        "Users": [ { "UserName": "ana" } ],
+        "Roles": [
+            {
+                "RoleName": "Deploy",
+                "AssumeRolePolicyDocument": {
+                    "Statement": [
+                        {
+                            "Effect": "Allow",
+                            "Principal": { "AWS": "arn:aws:iam::111122223333:root" },
+                            "Action": "sts:AssumeRole"
+                        }
+                    ]
+                }
+            }
+        ]
+    }
+    })
+    .unwrap();
+    let chain = scenario
+        .assume_role_chain(
+            user("arn:aws:iam::111122223333:user/ana"),
+            &[AssumeRoleRequest::new("Deploy")],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+    let hop = &chain.hops[0];
+    assert!(!hop.identity_allows);
+    assert!(hop.trust_allows);
+    assert!(hop
+        .denied_because
+        .as_ref()
+        .unwrap()
+        .contains("identity policies"));
+}
+
+/// A role in each of three accounts: Mateo in the first account can assume Build in
+/// the second, which can assume Publish in the third.
+fn cross_account_scenario() -> Scenario {
+    let assume_role = |resource: &str| {
+        json!({
+            "Statement": [
+                { "Effect": "Allow", "Action": "sts:AssumeRole", "Resource": resource }
+            ]
+        })
+    };
+    let trust = |principal: &str| {
+        json!({
+            "Statement": [
+                { "Effect": "Allow", "Principal": { "AWS": principal }, "Action": "sts:AssumeRole" }
+            ]
+        })
+    };
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {},
+        "Users": [
+            {
+                "UserName": "mateo",
+                "AccountId": "111122223333",
+                "InlinePolicies": {
+                    "AssumeBuild": assume_role("arn:aws:iam::222233334444:role/Build")
+                }
+            }
+        ],
+        "Roles": [
+            {
+                "RoleName": "Build",
+                "AccountId": "222233334444",
+                "AssumeRolePolicyDocument": trust(MATEO),
+                "InlinePolicies": {
+                    "AssumePublish": assume_role("arn:aws:iam::333344445555:role/Publish")
+                }
+            },
+            {
+                "RoleName": "Publish",
+                "AccountId": "333344445555",
+                "AssumeRolePolicyDocument": trust("arn:aws:iam::222233334444:role/Build")
+            },
+            {
+                "RoleName": "Audit",
+                "AccountId": "333344445555",
+                "AssumeRolePolicyDocument": trust("arn:aws:iam::222233334444:role/Build")
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+#[test]
+fn role_chain_crosses_accounts_by_arn() {
+    let scenario = cross_account_scenario();
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest::new("arn:aws:iam::222233334444:role/Build"),
+                AssumeRoleRequest::new("arn:aws:iam::333344445555:role/Publish"),
+            ],
+        )
+        .unwrap();
+    assert!(chain.is_allowed(), "{chain:#?}");
+    assert_eq!(
+        chain.hops[1].role_arn,
+        "arn:aws:iam::333344445555:role/Publish"
+    );
+    assert_eq!(
+        chain.session.unwrap().principal,
+        Principal::ARN("arn:aws:sts::333344445555:assumed-role/Publish/iamthat".to_owned())
+    );
+}
+
+#[test]
+fn cross_account_hop_needs_identity_policy_and_trust() {
+    let scenario = cross_account_scenario();
+    // Build's trust policy names Mateo, but in another account that's not enough
+    // without an identity policy: Build has none allowing it to assume Audit.
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest::new("arn:aws:iam::222233334444:role/Build"),
+                AssumeRoleRequest::new("arn:aws:iam::333344445555:role/Audit"),
+            ],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+    assert!(chain.hops[0].is_allowed());
+    assert!(!chain.hops[1].identity_allows);
+    assert!(chain.hops[1].trust_allows);
+}
+
+#[test]
+fn role_name_is_looked_up_in_the_callers_account() {
+    let scenario = cross_account_scenario();
+    let err = scenario
+        .assume_role_chain(user(MATEO), &[AssumeRoleRequest::new("Build")])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("\"Build\""), "{err}");
+}
+
+#[test]
+fn chain_stops_at_first_denied_hop() {
+    let scenario = scenario_with_role_chain();
+    // Mateo can't directly assume Release, so the chain stops there.
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest::new("Release"),
+                AssumeRoleRequest::new("Deploy"),
+            ],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+    assert_eq!(chain.hops.len(), 1);
+}
+
+#[test]
+fn explicit_deny_in_trust_policy() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest::new("Deploy"),
+                AssumeRoleRequest {
+                    role_session_name: Some("forbidden".to_owned()),
+                    ..AssumeRoleRequest::new("Release")
+                },
+            ],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+    let hop = &chain.hops[1];
+    assert!(hop.trust_allows);
+    assert!(hop
+        .denied_because
+        .as_ref()
+        .unwrap()
+        .contains("Explicit deny"));
+}
+
+#[test]
+fn transitive_tags_may_not_be_overridden() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[
+                AssumeRoleRequest {
+                    tags: vec![tag("project", "widget")],
+                    transitive_tag_keys: vec!["project".to_owned()],
+                    ..AssumeRoleRequest::new("Deploy")
+                },
+                AssumeRoleRequest {
+                    tags: vec![tag("Project", "gadget")],
+                    ..AssumeRoleRequest::new("Release")
+                },
+            ],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+    assert!(chain.hops[1]
+        .denied_because
+        .as_ref()
+        .unwrap()
+        .contains("can't override a transitive tag"));
+}
+
+#[test]
+fn transitive_tag_keys_must_be_session_tags() {
+    let scenario = scenario_with_role_chain();
+    let chain = scenario
+        .assume_role_chain(
+            user(MATEO),
+            &[AssumeRoleRequest {
+                transitive_tag_keys: vec!["project".to_owned()],
+                ..AssumeRoleRequest::new("Deploy")
+            }],
+        )
+        .unwrap();
+    assert!(!chain.is_allowed());
+}
+
+#[test]
+fn session_duration_limits() {
+    let scenario = scenario_with_role_chain();
+    let assume = |duration_seconds: u32| AssumeRoleRequest {
+        duration_seconds: Some(duration_seconds),
+        ..AssumeRoleRequest::new("Deploy")
+    };
+    let allowed = |steps: &[AssumeRoleRequest]| {
+        scenario
+            .assume_role_chain(user(MATEO), steps)
+            .unwrap()
+            .is_allowed()
+    };
+    assert!(allowed(&[assume(7200)]));
+    assert!(!allowed(&[assume(7201)]));
+    assert!(!allowed(&[assume(600)]));
+    // Role chaining is limited to one hour.
+    assert!(!allowed(&[
+        assume(3600),
+        AssumeRoleRequest {
+            duration_seconds: Some(7200),
+            ..AssumeRoleRequest::new("Release")
+        }
+    ]));
+}
+
+#[test]
+fn max_session_duration_must_be_between_one_and_twelve_hours() {
+    for max_session_duration in [600, 50000] {
+        let err = Scenario::from_json_value(json! {
+        {
+            "NamedPolicies": {},
+            "Roles": [
+                {
+                    "RoleName": "Deploy",
+                    "AssumeRolePolicyDocument": { "Statement": [] },
+                    "MaxSessionDuration": max_session_duration
+                }
+            ]
+        }
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains(&format!(
+                "MaxSessionDuration {max_session_duration} is not between 3600 and 43200 seconds"
+            )),
+            "{err}"
+        );
+    }
+}
+
+#[test]
+fn undefined_role_is_an_error() {
+    let scenario = scenario_with_role_chain();
+    let err = scenario
+        .assume_role_chain(user(MATEO), &[AssumeRoleRequest::new("NoSuchRole")])
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("\"NoSuchRole\""), "{err}");
+}
+
+#[test]
+fn service_principal_can_not_assume_roles() {
+    let scenario = scenario_with_role_chain();
+    let err = scenario
+        .assume_role_chain(
+            Session::new(Principal::Service("ec2.amazonaws.com".to_owned())),
+            &[AssumeRoleRequest::new("Deploy")],
+        )
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::AssumeRoleFromService { service } if service == "ec2.amazonaws.com"),
+        "{err:?}"
+    );
+}
@@ -0,0 +1,224 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for permissions boundaries on users and roles.
+
+use serde_json::json;
+
+use iamthat::decision::Outcome;
+use iamthat::json::FromJson;
+use iamthat::policy::{Policy, SessionPolicy};
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::Request;
+
+const DEV: &str = "arn:aws:iam::111122223333:user/dev";
+const APP_SESSION: &str = "arn:aws:sts::111122223333:assumed-role/App/app-1";
+
+/// The user `dev` and the role `App` both have full access from their identity
+/// policies, limited by a boundary that allows only S3 access to `dev-` buckets.
+fn scenario_with_boundaries() -> Scenario {
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "AdminAccess": {
+                "Statement": [
+                    { "Effect": "Allow", "Action": "*", "Resource": "*" }
+                ]
+            },
+            "DeveloperBoundary": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:*",
+                        "Resource": "arn:aws:s3:::dev-*"
+                    },
+                    {
+                        "Effect": "Deny",
+                        "Action": "s3:DeleteBucket",
+                        "Resource": "*"
+                    }
+                ]
+            }
+        },
+        "ResourcePolicies": {
+            "Reports": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Principal": { "AWS": [DEV, APP_SESSION] },
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::prod-reports/*"
+                    }
+                ]
+            },
+            "Logs": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Principal": { "AWS": "arn:aws:iam::111122223333:role/App" },
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::prod-logs/*"
+                    }
+                ]
+            }
+        },
+        "Users": [
+            {
+                "UserName": "dev",
+                "AttachedPolicies": ["AdminAccess"],
+                "PermissionsBoundary": "DeveloperBoundary"
+            },
+            {
+                "UserName": "admin",
+                "AttachedPolicies": ["AdminAccess"]
+            }
+        ],
+        "Roles": [
+            {
+                "RoleName": "App",
+                "AssumeRolePolicyDocument": { "Statement": [] },
+                "AttachedPolicies": ["AdminAccess"],
+                "PermissionsBoundary": "DeveloperBoundary"
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+#[test]
+fn boundary_limits_identity_policies() {
+    let scenario = scenario_with_boundaries();
+    for principal in [DEV, APP_SESSION] {
+        let allowed = |action: &str, resource: &str| {
+            scenario
+                .eval(&Request::new(
+                    Principal::ARN(principal.to_owned()),
+                    action,
+                    resource,
+                ))
+                .unwrap()
+                .is_allow()
+        };
+        assert!(allowed("s3:PutObject", "arn:aws:s3:::dev-scratch/a"));
+        assert!(!allowed("s3:PutObject", "arn:aws:s3:::prod-data/a"));
+        assert!(!allowed(
+            "iam:CreateUser",
+            "arn:aws:iam::111122223333:user/dev2"
+        ));
+        // Denied by the boundary, even though it's within the boundary's allow.
+        assert!(!allowed("s3:DeleteBucket", "arn:aws:s3:::dev-scratch"));
+    }
+}
+
+#[test]
+fn boundary_applies_only_to_its_identity() {
+    let scenario = scenario_with_boundaries();
+    let admin = "arn:aws:iam::111122223333:user/admin";
+    assert!(scenario
+        .eval(&Request::new(
+            Principal::ARN(admin.to_owned()),
+            "iam:CreateUser",
+            "arn:aws:iam::111122223333:user/dev2"
+        ))
+        .unwrap()
+        .is_allow());
+    assert!(scenario
+        .eval(&Request::new(
+            Principal::ARN(admin.to_owned()),
+            "s3:DeleteBucket",
+            "arn:aws:s3:::dev-scratch"
+        ))
+        .unwrap()
+        .is_allow());
+}
+
+#[test]
+fn resource_policy_naming_user_or_session_is_not_limited_by_boundary() {
+    let scenario = scenario_with_boundaries();
+    for principal in [DEV, APP_SESSION] {
+        assert!(
+            scenario
+                .eval(&Request::new(
+                    Principal::ARN(principal.to_owned()),
+                    "s3:GetObject",
+                    "arn:aws:s3:::prod-reports/q3.csv"
+                ))
+                .unwrap()
+                .is_allow(),
+            "{principal}"
+        );
+    }
+}
+
+#[test]
+fn resource_policy_naming_user_is_still_limited_by_session_policy() {
+    let scenario = scenario_with_boundaries();
+    let put_only = Policy::from_json_value(json!({
+        "Statement": [
+            { "Effect": "Allow", "Action": "s3:PutObject", "Resource": "*" }
+        ]
+    }))
+    .unwrap();
+    let decision = scenario
+        .eval(&Request {
+            session_policies: vec![SessionPolicy::Inline(put_only)],
+            ..Request::new(
+                Principal::ARN(DEV.to_owned()),
+                "s3:GetObject",
+                "arn:aws:s3:::prod-reports/q3.csv",
+            )
+        })
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::ImplicitDeny);
+    assert_eq!(decision.reason, "Not allowed by any session policy");
+}
+
+#[test]
+fn resource_policy_naming_role_is_limited_by_boundary() {
+    let scenario = scenario_with_boundaries();
+    assert!(scenario
+        .eval(&Request::new(
+            Principal::ARN(APP_SESSION.to_owned()),
+            "s3:GetObject",
+            "arn:aws:s3:::prod-logs/today.log"
+        ))
+        .unwrap()
+        .is_deny());
+}
+
+#[test]
+fn boundary_is_found_for_users_and_role_sessions() {
+    let scenario = scenario_with_boundaries();
+    for principal in [DEV, APP_SESSION] {
+        let (name, _policy) = scenario
+            .permissions_boundary(&Principal::ARN(principal.to_owned()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "DeveloperBoundary");
+    }
+    assert!(scenario
+        .permissions_boundary(&Principal::ARN(
+            "arn:aws:iam::111122223333:user/admin".to_owned()
+        ))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn undefined_boundary_is_an_error() {
+    let mut scenario = scenario_with_boundaries();
+    scenario.users[0].permissions_boundary = Some("NoSuchPolicy".to_owned());
+    let err = scenario
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:GetObject",
+            "arn:aws:s3:::dev-scratch/a",
+        ))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "User \"dev\" has permissions boundary \"NoSuchPolicy\" which is not defined in the scenario"
+    );
+}
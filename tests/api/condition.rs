@@ -0,0 +1,582 @@
+// Copyright 2023 Martin Pool
+
+use eyre::Result;
+use serde_json::json;
+
+use iamthat::condition::{Condition, ConditionOperator, Operator, SetOperator};
+use iamthat::context::Context;
+use iamthat::policy::Policy;
+use iamthat::principal::Principal;
+use iamthat::variable::VARIABLES_VERSION;
+use iamthat::Request;
+
+/// Evaluate a condition block, given as JSON, against a context given as JSON.
+fn eval(condition: serde_json::Value, context: serde_json::Value) -> bool {
+    let condition: Condition = serde_json::from_value(condition).expect("parse condition");
+    let context: Context = serde_json::from_value(context).expect("parse context");
+    condition.matches(&context, Some(VARIABLES_VERSION))
+}
+
+#[test]
+fn empty_condition_matches() {
+    assert!(eval(json!({}), json!({})));
+}
+
+#[test]
+fn unknown_operator_is_an_error() {
+    let err = serde_json::from_value::<Condition>(json!({
+        "StringEqualz": { "aws:username": "mateo" }
+    }))
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains(r#"Invalid condition operator "StringEqualz": unknown condition operator"#),
+        "{err}"
+    );
+}
+
+#[test]
+fn operator_names_round_trip() {
+    for op in Operator::ALL {
+        assert_eq!(op.name().parse::<Operator>().unwrap(), *op);
+    }
+}
+
+#[test]
+fn string_equals() {
+    let condition = json!({ "StringEquals": { "aws:username": ["mateo", "alice"] } });
+    assert!(eval(condition.clone(), json!({ "aws:username": "mateo" })));
+    assert!(eval(condition.clone(), json!({ "aws:username": "alice" })));
+    assert!(!eval(condition.clone(), json!({ "aws:username": "Mateo" })));
+    assert!(!eval(condition, json!({})));
+}
+
+#[test]
+fn condition_keys_are_case_insensitive() {
+    assert!(eval(
+        json!({ "StringEquals": { "AWS:UserName": "mateo" } }),
+        json!({ "aws:username": "mateo" })
+    ));
+}
+
+#[test]
+fn string_not_equals_matches_missing_key() {
+    let condition = json!({ "StringNotEquals": { "aws:username": ["mateo", "alice"] } });
+    assert!(!eval(condition.clone(), json!({ "aws:username": "mateo" })));
+    assert!(eval(condition.clone(), json!({ "aws:username": "bob" })));
+    assert!(eval(condition, json!({})));
+}
+
+#[test]
+fn string_equals_ignore_case() {
+    let condition = json!({ "StringEqualsIgnoreCase": { "aws:username": "Mateo" } });
+    assert!(eval(condition.clone(), json!({ "aws:username": "MATEO" })));
+    assert!(!eval(
+        json!({ "StringNotEqualsIgnoreCase": { "aws:username": "Mateo" } }),
+        json!({ "aws:username": "mateo" })
+    ));
+}
+
+#[test]
+fn string_like() {
+    let condition = json!({ "StringLike": { "s3:prefix": ["home/mateo/*", "public/????"] } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "s3:prefix": "home/mateo/docs/a.txt" })
+    ));
+    assert!(eval(
+        condition.clone(),
+        json!({ "s3:prefix": "public/abcd" })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "s3:prefix": "public/abcde" })
+    ));
+    assert!(!eval(condition, json!({ "s3:prefix": "home/alice/" })));
+    assert!(eval(
+        json!({ "StringNotLike": { "s3:prefix": "home/*" } }),
+        json!({ "s3:prefix": "tmp/" })
+    ));
+}
+
+#[test]
+fn numeric_operators() {
+    let context = json!({ "aws:MultiFactorAuthAge": "3600" });
+    assert!(eval(
+        json!({ "NumericLessThan": { "aws:MultiFactorAuthAge": 7200 } }),
+        context.clone()
+    ));
+    assert!(!eval(
+        json!({ "NumericLessThan": { "aws:MultiFactorAuthAge": "3600" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "NumericLessThanEquals": { "aws:MultiFactorAuthAge": "3600" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "NumericGreaterThan": { "aws:MultiFactorAuthAge": "60.5" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "NumericGreaterThanEquals": { "aws:MultiFactorAuthAge": "3600.0" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "NumericEquals": { "aws:MultiFactorAuthAge": "3600" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "NumericNotEquals": { "aws:MultiFactorAuthAge": "10" } }),
+        context.clone()
+    ));
+    // Values that aren't numbers never match.
+    assert!(!eval(
+        json!({ "NumericEquals": { "aws:MultiFactorAuthAge": "lots" } }),
+        context
+    ));
+}
+
+#[test]
+fn numeric_operators_reject_infinity_and_nan() {
+    let context = json!({ "count": "5" });
+    for value in ["inf", "-inf", "infinity", "NaN"] {
+        for operator in ["NumericLessThan", "NumericGreaterThan", "NumericEquals"] {
+            assert!(
+                !eval(json!({ operator: { "count": value } }), context.clone()),
+                "{operator} {value}"
+            );
+        }
+    }
+    assert!(!eval(
+        json!({ "NumericEquals": { "count": "NaN" } }),
+        json!({ "count": "NaN" })
+    ));
+}
+
+#[test]
+fn date_operators() {
+    let context = json!({ "aws:CurrentTime": "2023-06-01T12:00:00Z" });
+    assert!(eval(
+        json!({ "DateLessThan": { "aws:CurrentTime": "2023-06-02" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "DateGreaterThan": { "aws:CurrentTime": "2023-06-01T11:59:59Z" } }),
+        context.clone()
+    ));
+    assert!(!eval(
+        json!({ "DateGreaterThan": { "aws:CurrentTime": "2023-06-01T12:00:00Z" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "DateGreaterThanEquals": { "aws:CurrentTime": "2023-06-01T12:00:00Z" } }),
+        context.clone()
+    ));
+    // The same instant expressed with an offset, or as epoch seconds.
+    assert!(eval(
+        json!({ "DateEquals": { "aws:CurrentTime": "2023-06-01T22:00:00+10:00" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "DateEquals": { "aws:CurrentTime": "1685620800" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "DateLessThanEquals": { "aws:CurrentTime": "2023-06-01T12:00:00.000Z" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "DateNotEquals": { "aws:CurrentTime": "2023-01-01" } }),
+        context
+    ));
+}
+
+#[test]
+fn date_with_hour_24_is_invalid() {
+    let context = json!({ "aws:CurrentTime": "2023-06-02T00:00:00Z" });
+    for operator in ["DateEquals", "DateLessThanEquals", "DateGreaterThanEquals"] {
+        assert!(
+            !eval(
+                json!({ operator: { "aws:CurrentTime": "2023-06-01T24:00:00Z" } }),
+                context.clone()
+            ),
+            "{operator}"
+        );
+    }
+}
+
+#[test]
+fn bool_operator() {
+    let condition = json!({ "Bool": { "aws:SecureTransport": false } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:SecureTransport": "false" })
+    ));
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:SecureTransport": false })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:SecureTransport": "true" })
+    ));
+    assert!(!eval(condition, json!({})));
+}
+
+#[test]
+fn binary_equals() {
+    let condition = json!({ "BinaryEquals": { "key": "QmluYXJ5VmFsdWU=" } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "key": "QmluYXJ5VmFsdWU=" })
+    ));
+    assert!(!eval(condition, json!({ "key": "qmluyxj5vmfsdwu=" })));
+}
+
+#[test]
+fn binary_equals_compares_decoded_bytes() {
+    // Both sides must be valid base64: identical text that isn't base64 is not equal.
+    assert!(!eval(
+        json!({ "BinaryEquals": { "key": "not base64!" } }),
+        json!({ "key": "not base64!" })
+    ));
+    assert!(!eval(
+        json!({ "BinaryEquals": { "key": "QmluYXJ5VmFsdWU" } }),
+        json!({ "key": "QmluYXJ5VmFsdWU" })
+    ));
+    assert!(eval(
+        json!({ "BinaryEquals": { "key": ["AAEC", "/w=="] } }),
+        json!({ "key": "/w==" })
+    ));
+}
+
+#[test]
+fn ip_address() {
+    let condition = json!({ "IpAddress": { "aws:SourceIp": ["203.0.113.0/24", "2001:db8::/32"] } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:SourceIp": "203.0.113.7" })
+    ));
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:SourceIp": "2001:db8:1::7" })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:SourceIp": "203.0.114.7" })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:SourceIp": "not an ip" })
+    ));
+    assert!(!eval(condition, json!({})));
+    assert!(eval(
+        json!({ "IpAddress": { "aws:SourceIp": "198.51.100.1" } }),
+        json!({ "aws:SourceIp": "198.51.100.1" })
+    ));
+    assert!(eval(
+        json!({ "NotIpAddress": { "aws:SourceIp": "203.0.113.0/24" } }),
+        json!({ "aws:SourceIp": "198.51.100.1" })
+    ));
+}
+
+#[test]
+fn arn_operators() {
+    let context = json!({ "aws:SourceArn": "arn:aws:sns:us-east-1:111122223333:topic" });
+    assert!(eval(
+        json!({ "ArnLike": { "aws:SourceArn": "arn:aws:sns:*:111122223333:*" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "ArnEquals": { "aws:SourceArn": "arn:aws:sns:us-east-1:111122223333:topic" } }),
+        context.clone()
+    ));
+    assert!(eval(
+        json!({ "ArnNotLike": { "aws:SourceArn": "arn:aws:sns:*:444455556666:*" } }),
+        context.clone()
+    ));
+    assert!(!eval(
+        json!({ "ArnNotEquals": { "aws:SourceArn": "arn:aws:sns:us-east-1:111122223333:topic" } }),
+        context
+    ));
+}
+
+#[test]
+fn null_operator() {
+    let condition = json!({ "Null": { "aws:TokenIssueTime": "true" } });
+    assert!(eval(condition.clone(), json!({})));
+    assert!(!eval(
+        condition,
+        json!({ "aws:TokenIssueTime": "2023-06-01T00:00:00Z" })
+    ));
+    let condition = json!({ "Null": { "aws:TokenIssueTime": false } });
+    assert!(!eval(condition.clone(), json!({})));
+    assert!(eval(
+        condition,
+        json!({ "aws:TokenIssueTime": "2023-06-01T00:00:00Z" })
+    ));
+}
+
+#[test]
+fn all_operators_and_keys_must_match() {
+    let condition = json!({
+        "StringEquals": { "aws:username": "mateo", "aws:PrincipalTag/team": "dev" },
+        "Bool": { "aws:SecureTransport": "true" }
+    });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:username": "mateo", "aws:PrincipalTag/team": "dev", "aws:SecureTransport": "true" })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:username": "mateo", "aws:PrincipalTag/team": "ops", "aws:SecureTransport": "true" })
+    ));
+    assert!(!eval(
+        condition,
+        json!({ "aws:username": "mateo", "aws:PrincipalTag/team": "dev", "aws:SecureTransport": "false" })
+    ));
+}
+
+#[test]
+fn if_exists_matches_missing_key() {
+    let condition =
+        json!({ "StringEqualsIfExists": { "ec2:InstanceType": ["t3.micro", "t3.small"] } });
+    assert!(eval(condition.clone(), json!({})));
+    assert!(eval(
+        condition.clone(),
+        json!({ "ec2:InstanceType": "t3.micro" })
+    ));
+    assert!(!eval(
+        condition,
+        json!({ "ec2:InstanceType": "p4d.24xlarge" })
+    ));
+}
+
+#[test]
+fn negated_if_exists() {
+    let condition = json!({ "StringNotEqualsIfExists": { "aws:RequestedRegion": "us-east-1" } });
+    assert!(eval(condition.clone(), json!({})));
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:RequestedRegion": "eu-west-1" })
+    ));
+    assert!(!eval(
+        condition,
+        json!({ "aws:RequestedRegion": "us-east-1" })
+    ));
+}
+
+#[test]
+fn for_all_values_requires_every_request_value_to_match() {
+    let condition = json!({ "ForAllValues:StringEquals": { "aws:TagKeys": ["team", "project"] } });
+    assert!(eval(condition.clone(), json!({ "aws:TagKeys": ["team"] })));
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["project", "team"] })
+    ));
+    assert!(!eval(
+        condition,
+        json!({ "aws:TagKeys": ["team", "cost-center"] })
+    ));
+}
+
+#[test]
+fn for_all_values_with_missing_key_is_true() {
+    // This is the notorious case: a request with no tags at all satisfies a
+    // `ForAllValues` condition, which is why it is usually combined with a
+    // `Null` check.
+    let condition = json!({ "ForAllValues:StringEquals": { "aws:TagKeys": ["team"] } });
+    assert!(eval(condition.clone(), json!({})));
+    assert!(eval(condition.clone(), json!({ "aws:TagKeys": [] })));
+    let guarded = json!({
+        "ForAllValues:StringEquals": { "aws:TagKeys": ["team"] },
+        "Null": { "aws:TagKeys": "false" }
+    });
+    assert!(!eval(guarded.clone(), json!({})));
+    assert!(eval(guarded, json!({ "aws:TagKeys": ["team"] })));
+}
+
+#[test]
+fn for_all_values_with_an_empty_string_is_not_an_empty_set() {
+    let condition = json!({ "ForAllValues:StringEquals": { "aws:TagKeys": ["team"] } });
+    assert!(!eval(condition.clone(), json!({ "aws:TagKeys": [""] })));
+    assert!(!eval(condition, json!({ "aws:TagKeys": "" })));
+    let condition = json!({ "ForAllValues:StringLike": { "aws:TagKeys": ["", "team"] } });
+    assert!(eval(condition, json!({ "aws:TagKeys": [""] })));
+}
+
+#[test]
+fn for_any_value_requires_some_request_value_to_match() {
+    let condition = json!({ "ForAnyValue:StringEquals": { "aws:TagKeys": ["team", "project"] } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["cost-center", "team"] })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["cost-center"] })
+    ));
+    assert!(!eval(condition.clone(), json!({ "aws:TagKeys": [] })));
+    assert!(!eval(condition, json!({})));
+}
+
+#[test]
+fn for_any_value_if_exists_matches_missing_key() {
+    let condition = json!({ "ForAnyValue:StringEqualsIfExists": { "aws:TagKeys": "team" } });
+    assert!(eval(condition.clone(), json!({})));
+    assert!(!eval(condition, json!({ "aws:TagKeys": ["project"] })));
+}
+
+#[test]
+fn for_all_values_with_negated_operator() {
+    // None of the request's tag keys may be in the forbidden list.
+    let condition =
+        json!({ "ForAllValues:StringNotEquals": { "aws:TagKeys": ["owner", "admin"] } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["team", "project"] })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["team", "admin"] })
+    ));
+    assert!(eval(condition, json!({})));
+}
+
+#[test]
+fn for_any_value_with_negated_operator() {
+    // At least one of the request's tag keys must not be in the list.
+    let condition = json!({ "ForAnyValue:StringNotEquals": { "aws:TagKeys": ["owner"] } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["owner", "team"] })
+    ));
+    assert!(!eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["owner"] })
+    ));
+    assert!(!eval(condition, json!({})));
+}
+
+#[test]
+fn for_any_value_string_like() {
+    let condition = json!({ "ForAnyValue:StringLike": { "aws:TagKeys": "project-*" } });
+    assert!(eval(
+        condition.clone(),
+        json!({ "aws:TagKeys": ["team", "project-x"] })
+    ));
+    assert!(!eval(condition, json!({ "aws:TagKeys": ["team"] })));
+}
+
+#[test]
+fn parse_qualified_operators() {
+    assert_eq!(
+        "ForAllValues:StringLikeIfExists"
+            .parse::<ConditionOperator>()
+            .unwrap(),
+        ConditionOperator {
+            set_operator: Some(SetOperator::ForAllValues),
+            operator: Operator::StringLike,
+            if_exists: true,
+        }
+    );
+    assert_eq!(
+        "ForAnyValue:StringEquals"
+            .parse::<ConditionOperator>()
+            .unwrap(),
+        ConditionOperator {
+            set_operator: Some(SetOperator::ForAnyValue),
+            operator: Operator::StringEquals,
+            if_exists: false,
+        }
+    );
+    assert_eq!(
+        "NumericLessThanIfExists"
+            .parse::<ConditionOperator>()
+            .unwrap(),
+        ConditionOperator {
+            set_operator: None,
+            operator: Operator::NumericLessThan,
+            if_exists: true,
+        }
+    );
+    for s in [
+        "ForAllValues:StringLikeIfExists",
+        "ForAnyValue:ArnEquals",
+        "DateLessThanIfExists",
+        "Bool",
+    ] {
+        assert_eq!(s.parse::<ConditionOperator>().unwrap().to_string(), s);
+    }
+}
+
+#[test]
+fn invalid_qualified_operators() {
+    for s in [
+        "NullIfExists",
+        "ForAllValues:Null",
+        "ForSomeValues:StringEquals",
+        "StringEqualsIfExistsIfExists",
+        "IfExists",
+    ] {
+        assert!(s.parse::<ConditionOperator>().is_err(), "{s}");
+    }
+}
+
+#[test]
+fn qualified_condition_round_trips_through_json() {
+    let json = json!({
+        "ForAllValues:StringEquals": { "aws:TagKeys": ["project", "team"] },
+        "StringLikeIfExists": { "s3:prefix": ["home/*"] }
+    });
+    let condition: Condition = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&condition).unwrap(), json);
+}
+
+#[test]
+fn statement_condition_uses_request_context() -> Result<()> {
+    let policy: Policy = serde_json::from_value(json!({
+        "Statement": [
+            {
+                "Effect": "Deny",
+                "Action": "s3:*",
+                "Resource": "*",
+                "Condition": {
+                    "Bool": { "aws:SecureTransport": "false" }
+                }
+            }
+        ]
+    }))?;
+    let mut request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::production/key",
+    );
+    assert!(!policy.denies(&request));
+    request.context.insert("aws:SecureTransport", ["false"]);
+    assert!(policy.denies(&request));
+    request.context.insert("aws:SecureTransport", ["true"]);
+    assert!(!policy.denies(&request));
+    Ok(())
+}
+
+#[test]
+fn request_context_from_json() -> Result<()> {
+    let request: Request = serde_json::from_value(json!({
+        "Action": "s3:ListBucket",
+        "Principal": { "ARN": "arn:aws:iam::111122223333:user/mateo" },
+        "Resource": "arn:aws:s3:::production",
+        "Context": {
+            "aws:SecureTransport": true,
+            "s3:prefix": ["home/", "public/"]
+        }
+    }))?;
+    assert_eq!(
+        request.context.get("aws:securetransport"),
+        Some(["true".to_owned()].as_slice())
+    );
+    assert_eq!(request.context.get("s3:prefix").unwrap().len(), 2);
+    assert_eq!(request.context.get("aws:SourceIp"), None);
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// Copyright 2023 Martin Pool
+
+use serde_json::json;
+
+use iamthat::context::Context;
+use iamthat::Request;
+
+#[test]
+fn typed_context_accessors() {
+    let mut context = Context::new();
+    context.set_source_ip("203.0.113.7".parse().unwrap());
+    context.set_secure_transport(true);
+    context.set_current_time("2023-06-01T12:00:00Z").unwrap();
+    context.set_principal_tag("team", "dev");
+    context.set_request_tag("project", "iamthat");
+    context.set_tag_keys(["project", "team"]);
+    context.insert("s3:prefix", ["home/mateo/"]);
+
+    assert_eq!(context.source_ip(), Some("203.0.113.7".parse().unwrap()));
+    assert_eq!(context.secure_transport(), Some(true));
+    assert_eq!(context.current_time(), Some("2023-06-01T12:00:00Z"));
+    assert_eq!(context.principal_tag("team"), Some("dev"));
+    assert_eq!(context.principal_tag("TEAM"), Some("dev"));
+    assert_eq!(context.request_tag("project"), Some("iamthat"));
+    assert_eq!(context.tag_keys().unwrap(), ["project", "team"]);
+    assert_eq!(context.get_one("s3:prefix"), Some("home/mateo/"));
+    assert!(context.set_current_time("yesterday").is_err());
+
+    assert_eq!(
+        serde_json::to_value(&context).unwrap(),
+        json!({
+            "aws:CurrentTime": "2023-06-01T12:00:00Z",
+            "aws:PrincipalTag/team": "dev",
+            "aws:RequestTag/project": "iamthat",
+            "aws:SecureTransport": "true",
+            "aws:SourceIp": "203.0.113.7",
+            "aws:TagKeys": ["project", "team"],
+            "s3:prefix": "home/mateo/"
+        })
+    );
+}
+
+#[test]
+fn context_values_are_type_checked_when_loaded() {
+    for (context, message) in [
+        (json!({ "aws:SourceIp": "localhost" }), "aws:SourceIp"),
+        (
+            json!({ "aws:SecureTransport": "yes" }),
+            "aws:SecureTransport",
+        ),
+        (json!({ "aws:CurrentTime": "noon" }), "aws:CurrentTime"),
+        (
+            json!({ "aws:MultiFactorAuthAge": "old" }),
+            "aws:MultiFactorAuthAge",
+        ),
+        (
+            json!({ "aws:MultiFactorAuthAge": "NaN" }),
+            "aws:MultiFactorAuthAge",
+        ),
+        (
+            json!({ "aws:CurrentTime": "2023-06-01T24:00:00Z" }),
+            "aws:CurrentTime",
+        ),
+        (
+            json!({ "aws:PrincipalTag/team": ["dev", "ops"] }),
+            "must have exactly one value",
+        ),
+        (
+            json!({ "aws:username": "a", "AWS:UserName": "b" }),
+            "repeated with different case",
+        ),
+    ] {
+        let err = serde_json::from_value::<Request>(json!({
+            "Action": "s3:ListBucket",
+            "Principal": { "ARN": "arn:aws:iam::111122223333:user/mateo" },
+            "Context": context
+        }))
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains(message), "{err}");
+    }
+}
+
+#[test]
+fn other_context_keys_may_have_many_values() {
+    let context: Context = serde_json::from_value(json!({
+        "s3:prefix": ["home/", "public/"],
+        "aws:TagKeys": ["team", "project"],
+        "aws:MultiFactorAuthAge": 300,
+        "ec2:InstanceType": "t3.micro"
+    }))
+    .unwrap();
+    assert_eq!(context.get("s3:prefix").unwrap().len(), 2);
+    assert_eq!(context.tag_keys().unwrap().len(), 2);
+    assert_eq!(context.get_one("aws:MultiFactorAuthAge"), Some("300"));
+}
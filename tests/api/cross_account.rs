@@ -0,0 +1,185 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for requests whose principal and resource are in different accounts.
+
+use serde_json::json;
+
+use iamthat::json::FromJson;
+use iamthat::policy::Policy;
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::Request;
+
+const OWNER: &str = "111122223333";
+const CONSUMER: &str = "444455556666";
+const ARTIFACT: &str = "arn:aws:s3:::shared-artifacts/app-1.2.3.tar.gz";
+
+/// The `shared-artifacts` bucket is owned by the OWNER account. `builder` has an
+/// identity policy allowing it to read artifacts; `nobody` has no policies.
+fn scenario_with_shared_bucket() -> Scenario {
+    Scenario::from_json_value(json!({
+        "NamedPolicies": {
+            "ReadArtifacts": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::shared-artifacts/*"
+                    }
+                ]
+            }
+        },
+        "Users": [
+            { "UserName": "builder", "AttachedPolicies": ["ReadArtifacts"] },
+            { "UserName": "nobody" }
+        ],
+        "Resources": [
+            {
+                "Arn": ["arn:aws:s3:::shared-artifacts", "arn:aws:s3:::shared-artifacts/*"],
+                "AccountId": OWNER
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+fn bucket_policy_for(principal: &str) -> Policy {
+    Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": { "AWS": principal },
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::shared-artifacts/*"
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+fn get_artifact(account_id: &str, user_name: &str) -> Request {
+    Request::new(
+        Principal::ARN(format!("arn:aws:iam::{account_id}:user/{user_name}")),
+        "s3:GetObject",
+        ARTIFACT,
+    )
+}
+
+#[test]
+fn resource_account_from_scenario_or_arn() {
+    let scenario = scenario_with_shared_bucket();
+    assert_eq!(scenario.resource_account(ARTIFACT), Some(OWNER));
+    assert_eq!(
+        scenario.resource_account("arn:aws:s3:::shared-artifacts"),
+        Some(OWNER)
+    );
+    assert_eq!(
+        scenario.resource_account("arn:aws:sqs:us-east-1:444455556666:jobs"),
+        Some(CONSUMER)
+    );
+    assert_eq!(scenario.resource_account("arn:aws:s3:::other/key"), None);
+    assert_eq!(scenario.resource_account("*"), None);
+}
+
+#[test]
+fn resource_account_is_in_request_context() {
+    let scenario = scenario_with_shared_bucket();
+    let context = scenario.request_context(&get_artifact(CONSUMER, "builder"));
+    assert_eq!(context.get_one("aws:ResourceAccount"), Some(OWNER));
+    assert_eq!(context.get_one("aws:PrincipalAccount"), Some(CONSUMER));
+}
+
+#[test]
+fn cross_account_needs_resource_policy() {
+    let scenario = scenario_with_shared_bucket();
+    let request = get_artifact(CONSUMER, "builder");
+    assert!(scenario.is_cross_account(&request));
+    assert!(scenario.eval(&request).unwrap().is_deny());
+
+    // Within the owning account, the identity policy is enough.
+    let request = get_artifact(OWNER, "builder");
+    assert!(!scenario.is_cross_account(&request));
+    assert!(scenario.eval(&request).unwrap().is_allow());
+}
+
+#[test]
+fn cross_account_needs_identity_policy() {
+    let mut scenario = scenario_with_shared_bucket();
+    scenario.add_resource_policy("SharedArtifacts", bucket_policy_for(CONSUMER));
+    assert!(scenario
+        .eval(&get_artifact(CONSUMER, "builder"))
+        .unwrap()
+        .is_allow());
+    assert!(scenario
+        .eval(&get_artifact(CONSUMER, "nobody"))
+        .unwrap()
+        .is_deny());
+}
+
+#[test]
+fn cross_account_grant_to_exact_principal_still_needs_identity_policy() {
+    let mut scenario = scenario_with_shared_bucket();
+    let nobody = format!("arn:aws:iam::{CONSUMER}:user/nobody");
+    scenario.add_resource_policy("SharedArtifacts", bucket_policy_for(&nobody));
+    assert!(scenario
+        .eval(&get_artifact(CONSUMER, "nobody"))
+        .unwrap()
+        .is_deny());
+
+    // But in the same account, naming the principal is enough.
+    let mut scenario = scenario_with_shared_bucket();
+    let nobody = format!("arn:aws:iam::{OWNER}:user/nobody");
+    scenario.add_resource_policy("SharedArtifacts", bucket_policy_for(&nobody));
+    assert!(scenario
+        .eval(&get_artifact(OWNER, "nobody"))
+        .unwrap()
+        .is_allow());
+}
+
+#[test]
+fn unknown_resource_account_is_treated_as_same_account() {
+    let scenario = scenario_with_shared_bucket();
+    let request = Request {
+        resource: "arn:aws:s3:::other-bucket/key".to_owned(),
+        ..get_artifact(CONSUMER, "builder")
+    };
+    assert!(!scenario.is_cross_account(&request));
+}
+
+#[test]
+fn users_and_roles_are_only_found_in_their_own_account() {
+    let mut scenario = Scenario::from_json_value(json!({
+        "NamedPolicies": {},
+        "AccountId": OWNER,
+        "Users": [
+            { "UserName": "builder", "Tags": [ { "Key": "team", "Value": "ops" } ] },
+            { "UserName": "nobody", "AccountId": CONSUMER }
+        ],
+        "Roles": [
+            { "RoleName": "Deploy", "AssumeRolePolicyDocument": { "Statement": [] } }
+        ]
+    }))
+    .unwrap();
+    let user = |account_id: &str, user_name: &str| {
+        Principal::ARN(format!("arn:aws:iam::{account_id}:user/{user_name}"))
+    };
+    let session = |account_id: &str| {
+        Principal::ARN(format!("arn:aws:sts::{account_id}:assumed-role/Deploy/ci"))
+    };
+    assert!(scenario.find_user(&user(OWNER, "builder")).is_some());
+    assert!(scenario.find_user(&user(CONSUMER, "builder")).is_none());
+    assert!(scenario.find_user(&user(CONSUMER, "nobody")).is_some());
+    assert!(scenario.find_user(&user(OWNER, "nobody")).is_none());
+    assert!(scenario.find_role_for_session(&session(OWNER)).is_some());
+    assert!(scenario.find_role_for_session(&session(CONSUMER)).is_none());
+
+    // A principal with the same name in another account doesn't get the user's tags.
+    let context = scenario.request_context(&get_artifact(CONSUMER, "builder"));
+    assert_eq!(context.get_one("aws:PrincipalTag/team"), None);
+    assert_eq!(context.get_one("aws:username"), None);
+
+    // Without any account in the scenario, names match in every account.
+    scenario.account_id = None;
+    assert!(scenario.find_user(&user(CONSUMER, "builder")).is_some());
+    assert!(scenario.find_role_for_session(&session(CONSUMER)).is_some());
+}
@@ -0,0 +1,183 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for the `Decision` explaining the result of `Scenario::eval`.
+
+use serde_json::json;
+
+use iamthat::decision::{MatchedStatement, Outcome};
+use iamthat::effect::Effect;
+use iamthat::json::FromJson;
+use iamthat::policy::{Policy, PolicyType};
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::testcase::ExpectedOutcome;
+use iamthat::Request;
+
+const DEV: &str = "arn:aws:iam::111122223333:user/dev";
+const REPORTS: &str = "arn:aws:s3:::reports";
+
+/// The user `dev` has an identity policy allowing all of S3, except deleting buckets.
+fn scenario() -> Scenario {
+    Scenario::from_json_value(json!({
+        "NamedPolicies": {
+            "S3Access": {
+                "Statement": [
+                    { "Sid": "AllowS3", "Effect": "Allow", "Action": "s3:*", "Resource": "*" },
+                    { "Effect": "Deny", "Action": "s3:DeleteBucket", "Resource": "*" }
+                ]
+            }
+        },
+        "Users": [ { "UserName": "dev", "AttachedPolicies": ["S3Access"] } ],
+        "Roles": []
+    }))
+    .unwrap()
+}
+
+#[test]
+fn allow_names_the_allowing_statement() {
+    let decision = scenario()
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:ListBucket",
+            REPORTS,
+        ))
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::Allow);
+    assert_eq!(
+        decision.matched_statements,
+        [MatchedStatement {
+            policy_name: "S3Access".to_owned(),
+            policy_type: PolicyType::Identity,
+            statement_index: 0,
+            sid: Some("AllowS3".to_owned()),
+            effect: Effect::Allow,
+        }]
+    );
+    assert_eq!(
+        decision.to_string(),
+        r#"Allow: Allowed: statement 0 ("AllowS3") of identity policy "S3Access""#
+    );
+}
+
+#[test]
+fn explicit_deny_names_the_denying_statement() {
+    let decision = scenario()
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:DeleteBucket",
+            REPORTS,
+        ))
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::ExplicitDeny);
+    // Both statements match, but only the deny decided the result.
+    assert_eq!(decision.matched_statements.len(), 2);
+    let deciding = decision.deciding_statements().collect::<Vec<_>>();
+    assert_eq!(deciding.len(), 1);
+    assert_eq!(deciding[0].statement_index, 1);
+    assert_eq!(deciding[0].sid, None);
+    assert_eq!(
+        decision.to_string(),
+        r#"Explicit deny: Denied by a Deny statement: statement 1 of identity policy "S3Access""#
+    );
+}
+
+#[test]
+fn implicit_deny_has_no_matching_statements() {
+    let decision = scenario()
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "ec2:RunInstances",
+            REPORTS,
+        ))
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::ImplicitDeny);
+    assert!(decision.matched_statements.is_empty());
+    assert_eq!(
+        decision.to_string(),
+        "Implicit deny: Not allowed by any resource or identity policy"
+    );
+}
+
+#[test]
+fn statements_of_other_policy_types_are_named() {
+    let mut scenario = scenario();
+    scenario.add_resource_policy(
+        "ReportsBucket",
+        Policy::from_json_value(json!({
+            "Statement": [
+                {
+                    "Sid": "NoDeletes",
+                    "Effect": "Deny",
+                    "Principal": "*",
+                    "Action": "s3:Delete*",
+                    "Resource": "*"
+                }
+            ]
+        }))
+        .unwrap(),
+    );
+    let decision = scenario
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:DeleteBucket",
+            REPORTS,
+        ))
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::ExplicitDeny);
+    assert!(decision.matched_statements.contains(&MatchedStatement {
+        policy_name: "ReportsBucket".to_owned(),
+        policy_type: PolicyType::Resource,
+        statement_index: 0,
+        sid: Some("NoDeletes".to_owned()),
+        effect: Effect::Deny,
+    }));
+}
+
+#[test]
+fn decision_serializes_to_json() {
+    let decision = scenario()
+        .eval(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:ListBucket",
+            REPORTS,
+        ))
+        .unwrap();
+    assert_eq!(
+        serde_json::to_value(&decision).unwrap(),
+        json!({
+            "Outcome": "Allow",
+            "Reason": "Allowed",
+            "MatchedStatements": [
+                {
+                    "PolicyName": "S3Access",
+                    "PolicyType": "Identity",
+                    "StatementIndex": 0,
+                    "Sid": "AllowS3",
+                    "Effect": "Allow"
+                }
+            ]
+        })
+    );
+}
+
+#[test]
+fn expected_deny_matches_either_kind_of_deny() {
+    for (expected, allow, explicit_deny, implicit_deny) in [
+        (ExpectedOutcome::Allow, true, false, false),
+        (ExpectedOutcome::Deny, false, true, true),
+        (ExpectedOutcome::ExplicitDeny, false, true, false),
+        (ExpectedOutcome::ImplicitDeny, false, false, true),
+    ] {
+        assert_eq!(expected.matches(Outcome::Allow), allow, "{expected:?}");
+        assert_eq!(
+            expected.matches(Outcome::ExplicitDeny),
+            explicit_deny,
+            "{expected:?}"
+        );
+        assert_eq!(
+            expected.matches(Outcome::ImplicitDeny),
+            implicit_deny,
+            "{expected:?}"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for the structured `iamthat::Error`.
+
+use std::str::FromStr;
+
+use assert_fs::prelude::*;
+use assert_fs::NamedTempFile;
+use camino::Utf8Path;
+use serde_json::json;
+
+use iamthat::action::ActionGlob;
+use iamthat::arn::Arn;
+use iamthat::error::ReferenceKind;
+use iamthat::json::FromJson;
+use iamthat::policy::Policy;
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::{Error, Request};
+
+#[test]
+fn parse_error_has_json_pointer() {
+    let err = Policy::from_json_value(json!({
+        "Statement": [
+            { "Effect": "Allow", "Action": "*", "Resource": "*" },
+            { "Effect": "Perhaps", "Action": "*", "Resource": "*" }
+        ]
+    }))
+    .unwrap_err();
+    let Error::Parse {
+        path,
+        pointer,
+        error,
+    } = &err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(*path, None);
+    assert_eq!(pointer, "/Statement/1/Effect");
+    assert!(error.to_string().contains("unknown variant `Perhaps`"));
+    assert!(
+        err.to_string()
+            .starts_with("Failed to parse JSON at /Statement/1/Effect: unknown variant"),
+        "{err}"
+    );
+}
+
+#[test]
+fn parse_error_from_file_has_path() {
+    let file = NamedTempFile::new("policy.json").unwrap();
+    file.write_str(r#"{ "Statement": [ { "Effect": "Allow", "Action": "*", "Resource": 7 } ] }"#)
+        .unwrap();
+    let path = Utf8Path::from_path(file.path()).unwrap();
+    let err = Policy::from_json_file(path).unwrap_err();
+    match &err {
+        Error::Parse {
+            path: Some(err_path),
+            ..
+        } => assert_eq!(err_path, path),
+        _ => panic!("unexpected error {err:?}"),
+    }
+    assert!(
+        err.to_string()
+            .starts_with(&format!("Failed to parse JSON from {path}")),
+        "{err}"
+    );
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let err = Policy::from_json_file("example/policy/no_such_file.json".into()).unwrap_err();
+    assert!(matches!(err, Error::Io { .. }), "{err:?}");
+}
+
+#[test]
+fn invalid_arn_and_action_are_distinguished() {
+    assert!(matches!(
+        Arn::from_str("arn:aws:s3").unwrap_err(),
+        Error::InvalidArn { arn, .. } if arn == "arn:aws:s3"
+    ));
+    assert!(matches!(
+        ActionGlob::from_str("s3-GetObject").err(),
+        Some(Error::InvalidAction { action, .. }) if action == "s3-GetObject"
+    ));
+}
+
+#[test]
+fn undefined_reference_names_the_referrer_and_kind() {
+    let scenario = Scenario::from_json_value(json!({
+        "NamedPolicies": {},
+        "Users": [ { "UserName": "dev", "AttachedPolicies": ["Missing"] } ],
+        "Roles": []
+    }))
+    .unwrap();
+    let err = scenario
+        .eval(&Request::new(
+            Principal::ARN("arn:aws:iam::111122223333:user/dev".to_owned()),
+            "s3:GetObject",
+            "*",
+        ))
+        .unwrap_err();
+    let Error::Undefined {
+        referrer,
+        kind,
+        name,
+    } = &err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(referrer.as_deref(), Some("User \"dev\""));
+    assert_eq!(*kind, ReferenceKind::AttachedPolicy);
+    assert_eq!(name, "Missing");
+    assert_eq!(
+        err.to_string(),
+        "User \"dev\" has attached policy \"Missing\" which is not defined in the scenario"
+    );
+}
+
+#[test]
+fn invalid_action_glob_is_a_load_error() {
+    let file = NamedTempFile::new("policy.json").unwrap();
+    file.write_str(
+        r#"{ "Statement": [
+            { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" },
+            { "Effect": "Allow", "Action": ["s3:List Buckets"], "Resource": "*" }
+        ] }"#,
+    )
+    .unwrap();
+    let path = Utf8Path::from_path(file.path()).unwrap();
+    let err = Policy::from_json_file(path).unwrap_err();
+    let Error::Parse {
+        path: Some(err_path),
+        pointer,
+        error,
+    } = &err
+    else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(err_path, path);
+    assert_eq!(pointer, "/Statement/1/Action/0");
+    assert_eq!(
+        error.to_string(),
+        r#"Invalid action "s3:List Buckets": invalid action glob "List Buckets" at line 3 column 62"#
+    );
+}
+
+#[test]
+fn resource_that_is_not_an_arn_is_a_load_error() {
+    let err = Policy::from_json_value(json!({
+        "Statement": [ { "Effect": "Allow", "Action": "s3:*", "NotResource": "mybucket/*" } ]
+    }))
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Failed to parse JSON at /Statement/0/NotResource: Invalid ARN "mybucket/*": does not have six colon-separated segments"#
+    );
+}
+
+#[test]
+fn parse_errors_point_into_principal_action_and_resource_elements() {
+    let pointer = |statement: serde_json::Value| match Policy::from_json_value(
+        json!({ "Statement": [statement] }),
+    )
+    .unwrap_err()
+    {
+        Error::Parse { pointer, .. } => pointer,
+        err => panic!("unexpected error {err:?}"),
+    };
+    assert_eq!(
+        pointer(json!({ "Effect": "Allow", "Action": "s3:*", "Resource": 7 })),
+        "/Statement/0/Resource"
+    );
+    assert_eq!(
+        pointer(json!({ "Effect": "Allow", "NotAction": ["s3:*", "s3"], "Resource": "*" })),
+        "/Statement/0/NotAction/1"
+    );
+    assert_eq!(
+        pointer(json!({
+            "Effect": "Allow",
+            "Principal": { "AWS": 7 },
+            "Action": "s3:*",
+            "Resource": "*"
+        })),
+        "/Statement/0/Principal/AWS"
+    );
+    // Errors involving both elements of a pair point at the statement.
+    assert_eq!(
+        pointer(
+            json!({ "Effect": "Allow", "Action": "s3:*", "NotAction": "s3:*", "Resource": "*" })
+        ),
+        "/Statement/0"
+    );
+}
@@ -0,0 +1,163 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for the order in which `Scenario::eval` checks each type of policy.
+
+use serde_json::{json, Value};
+
+use iamthat::json::FromJson;
+use iamthat::policy::{Policy, SessionPolicy};
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::Request;
+
+const ACCOUNT: &str = "111122223333";
+const SESSION: &str = "arn:aws:sts::111122223333:assumed-role/App/app-1";
+const OBJECT: &str = "arn:aws:s3:::reports/q3.csv";
+
+fn allow(action: &str) -> Value {
+    json!({ "Statement": [ { "Effect": "Allow", "Action": action, "Resource": "*" } ] })
+}
+
+fn deny(action: &str) -> Value {
+    json!({ "Statement": [ { "Effect": "Deny", "Action": action, "Resource": "*" } ] })
+}
+
+/// A resource policy on the reports bucket granting `s3:GetObject` to a principal.
+fn grant_to(principal: &str) -> Policy {
+    Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": { "AWS": principal },
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::reports/*"
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+/// The role `App` has the given identity policy and optional boundary, in an account
+/// whose only SCP is the given policy.
+fn app_scenario(identity: Value, boundary: Option<Value>, scp: Value) -> Scenario {
+    let mut named_policies = json!({ "Identity": identity });
+    if let Some(boundary) = &boundary {
+        named_policies["Boundary"] = boundary.clone();
+    }
+    Scenario::from_json_value(json!({
+        "NamedPolicies": named_policies,
+        "ServiceControlPolicies": { "Guardrail": scp },
+        "Organization": {
+            "ManagementAccountId": "999999999999",
+            "Root": {
+                "Name": "Root",
+                "ServiceControlPolicies": ["Guardrail"],
+                "Accounts": [ { "AccountId": ACCOUNT, "ServiceControlPolicies": ["Guardrail"] } ]
+            }
+        },
+        "Users": [],
+        "Roles": [
+            {
+                "RoleName": "App",
+                "AssumeRolePolicyDocument": { "Statement": [] },
+                "AttachedPolicies": ["Identity"],
+                "PermissionsBoundary": boundary.map(|_| "Boundary")
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+fn get_object(session_policies: Vec<SessionPolicy>) -> Request {
+    Request {
+        session_policies,
+        ..Request::new(Principal::ARN(SESSION.to_owned()), "s3:GetObject", OBJECT)
+    }
+}
+
+fn session_policy(policy: Value) -> SessionPolicy {
+    SessionPolicy::Inline(Policy::from_json_value(policy).unwrap())
+}
+
+#[test]
+fn identity_allow_within_all_limits_is_allowed() {
+    let scenario = app_scenario(allow("s3:*"), Some(allow("s3:*")), allow("*"));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_allow());
+}
+
+#[test]
+fn explicit_deny_in_any_policy_type_overrides_allows() {
+    let mut scenario = app_scenario(allow("s3:*"), Some(allow("s3:*")), allow("*"));
+    scenario.add_resource_policy("Reports", grant_to(SESSION));
+    let request = get_object(vec![session_policy(deny("s3:GetObject"))]);
+    assert!(scenario.eval(&request).unwrap().is_deny());
+
+    let mut scenario = scenario_with_scp_deny();
+    scenario.add_resource_policy("Reports", grant_to(SESSION));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_deny());
+
+    let mut scenario = app_scenario(allow("s3:*"), Some(deny("s3:GetObject")), allow("*"));
+    scenario.add_resource_policy("Reports", grant_to(SESSION));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_deny());
+}
+
+fn scenario_with_scp_deny() -> Scenario {
+    app_scenario(
+        allow("s3:*"),
+        None,
+        json!({
+            "Statement": [
+                { "Effect": "Allow", "Action": "*", "Resource": "*" },
+                { "Effect": "Deny", "Action": "s3:GetObject", "Resource": "*" }
+            ]
+        }),
+    )
+}
+
+#[test]
+fn scps_limit_resource_policy_grants() {
+    let mut scenario = app_scenario(allow("s3:*"), None, allow("ec2:*"));
+    scenario.add_resource_policy("Reports", grant_to(SESSION));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_deny());
+}
+
+#[test]
+fn resource_policy_naming_session_needs_no_identity_allow() {
+    let mut scenario = app_scenario(allow("ec2:*"), Some(allow("ec2:*")), allow("*"));
+    scenario.add_resource_policy("Reports", grant_to(SESSION));
+    let request = get_object(vec![session_policy(allow("ec2:*"))]);
+    assert!(scenario.eval(&request).unwrap().is_allow());
+}
+
+#[test]
+fn resource_policy_naming_role_needs_no_identity_allow_but_is_limited() {
+    let role = "arn:aws:iam::111122223333:role/App";
+    let mut scenario = app_scenario(allow("ec2:*"), None, allow("*"));
+    scenario.add_resource_policy("Reports", grant_to(role));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_allow());
+
+    let request = get_object(vec![session_policy(allow("ec2:*"))]);
+    assert!(scenario.eval(&request).unwrap().is_deny());
+
+    let mut scenario = app_scenario(allow("ec2:*"), Some(allow("ec2:*")), allow("*"));
+    scenario.add_resource_policy("Reports", grant_to(role));
+    assert!(scenario.eval(&get_object(vec![])).unwrap().is_deny());
+}
+
+#[test]
+fn missing_identity_and_resource_allow_is_implicit_deny() {
+    let scenario = app_scenario(allow("ec2:*"), Some(allow("*")), allow("*"));
+    let request = get_object(vec![session_policy(allow("*"))]);
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+#[test]
+fn boundary_and_session_policy_must_both_allow() {
+    let scenario = app_scenario(allow("s3:*"), Some(allow("s3:*")), allow("*"));
+    let request = get_object(vec![session_policy(allow("ec2:*"))]);
+    assert!(scenario.eval(&request).unwrap().is_deny());
+
+    let scenario = app_scenario(allow("s3:*"), Some(allow("ec2:*")), allow("*"));
+    let request = get_object(vec![session_policy(allow("s3:*"))]);
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
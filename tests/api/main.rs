@@ -1,4 +1,19 @@
 // Copyright 2023 Martin Pool
 
+mod arn;
+mod assume;
+mod boundary;
+mod condition;
+mod context;
+mod cross_account;
+mod decision;
+mod error;
+mod eval;
+mod organization;
 mod policy;
+mod principal;
 mod scenario;
+mod schema;
+mod session_policy;
+mod trace;
+mod variable;
@@ -0,0 +1,407 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for service control policies and resource control policies in an
+//! organization.
+
+use serde_json::json;
+
+use iamthat::decision::Outcome;
+use iamthat::json::FromJson;
+use iamthat::policy::Policy;
+use iamthat::principal::Principal;
+use iamthat::resource::Resource;
+use iamthat::scenario::Scenario;
+use iamthat::Request;
+
+const MANAGEMENT: &str = "111111111111";
+const WORKLOAD: &str = "222233334444";
+const SANDBOX: &str = "555566667777";
+const UNATTACHED: &str = "888899990000";
+const OUTSIDE: &str = "444455556666";
+
+/// Every account has an admin user and role, and an SLR. The Sandbox OU only
+/// allows S3, and the workload account denies leaving the organization.
+fn scenario_with_organization() -> Scenario {
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "AdminAccess": {
+                "Statement": [ { "Effect": "Allow", "Action": "*", "Resource": "*" } ]
+            }
+        },
+        "ServiceControlPolicies": {
+            "FullAWSAccess": {
+                "Statement": [ { "Effect": "Allow", "Action": "*", "Resource": "*" } ]
+            },
+            "S3Only": {
+                "Statement": [ { "Effect": "Allow", "Action": "s3:*", "Resource": "*" } ]
+            },
+            "DenyLeaveOrganization": {
+                "Statement": [
+                    {
+                        "Effect": "Deny",
+                        "Action": "organizations:LeaveOrganization",
+                        "Resource": "*"
+                    }
+                ]
+            }
+        },
+        "Organization": {
+            "ManagementAccountId": MANAGEMENT,
+            "Root": {
+                "Name": "Root",
+                "ServiceControlPolicies": ["FullAWSAccess"],
+                "Accounts": [ { "AccountId": MANAGEMENT } ],
+                "OrganizationalUnits": [
+                    {
+                        "Name": "Workloads",
+                        "ServiceControlPolicies": ["FullAWSAccess"],
+                        "Accounts": [
+                            {
+                                "AccountId": WORKLOAD,
+                                "ServiceControlPolicies": ["FullAWSAccess", "DenyLeaveOrganization"]
+                            },
+                            { "AccountId": UNATTACHED }
+                        ],
+                        "OrganizationalUnits": [
+                            {
+                                "Name": "Sandbox",
+                                "ServiceControlPolicies": ["S3Only"],
+                                "Accounts": [
+                                    {
+                                        "AccountId": SANDBOX,
+                                        "ServiceControlPolicies": ["FullAWSAccess"]
+                                    }
+                                ]
+                            }
+                        ]
+                    }
+                ]
+            }
+        },
+        "Users": [ { "UserName": "admin", "AttachedPolicies": ["AdminAccess"] } ],
+        "Roles": [
+            {
+                "RoleName": "Admin",
+                "AssumeRolePolicyDocument": { "Statement": [] },
+                "AttachedPolicies": ["AdminAccess"]
+            },
+            {
+                "RoleName": "AWSServiceRoleForSupport",
+                "Path": "/aws-service-role/support.amazonaws.com/",
+                "AssumeRolePolicyDocument": { "Statement": [] },
+                "AttachedPolicies": ["AdminAccess"]
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+fn admin(account_id: &str) -> String {
+    format!("arn:aws:iam::{account_id}:user/admin")
+}
+
+fn outcome(scenario: &Scenario, principal_arn: &str, action: &str) -> Outcome {
+    scenario
+        .eval(&Request::new(
+            Principal::ARN(principal_arn.to_owned()),
+            action,
+            "*",
+        ))
+        .unwrap()
+        .outcome
+}
+
+fn allowed(scenario: &Scenario, principal_arn: &str, action: &str) -> bool {
+    outcome(scenario, principal_arn, action) == Outcome::Allow
+}
+
+#[test]
+fn path_to_account() {
+    let scenario = scenario_with_organization();
+    let organization = scenario.organization.as_ref().unwrap();
+    let names = organization
+        .path_to_account(SANDBOX)
+        .unwrap()
+        .iter()
+        .map(|level| level.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["Root", "Workloads", "Sandbox", SANDBOX]);
+    assert!(organization.path_to_account(OUTSIDE).is_none());
+}
+
+#[test]
+fn every_level_must_allow() {
+    let scenario = scenario_with_organization();
+    let sandbox_admin = admin(SANDBOX);
+    assert!(allowed(&scenario, &sandbox_admin, "s3:GetObject"));
+    // The Sandbox OU's SCP doesn't allow EC2, although nothing denies it.
+    assert_eq!(
+        outcome(&scenario, &sandbox_admin, "ec2:RunInstances"),
+        Outcome::ImplicitDeny
+    );
+    assert!(allowed(&scenario, &admin(WORKLOAD), "ec2:RunInstances"));
+}
+
+#[test]
+fn account_without_scps_allows_nothing() {
+    let scenario = scenario_with_organization();
+    assert_eq!(
+        outcome(&scenario, &admin(UNATTACHED), "s3:GetObject"),
+        Outcome::ImplicitDeny
+    );
+}
+
+#[test]
+fn scp_deny_applies_to_role_sessions() {
+    let scenario = scenario_with_organization();
+    let session = format!("arn:aws:sts::{WORKLOAD}:assumed-role/Admin/ops");
+    assert!(allowed(&scenario, &session, "ec2:RunInstances"));
+    for principal in [&session, &admin(WORKLOAD)] {
+        assert_eq!(
+            outcome(&scenario, principal, "organizations:LeaveOrganization"),
+            Outcome::ExplicitDeny
+        );
+    }
+}
+
+#[test]
+fn scps_do_not_apply_to_management_account() {
+    let scenario = scenario_with_organization();
+    assert!(scenario
+        .service_control_policies(&Principal::ARN(admin(MANAGEMENT)))
+        .unwrap()
+        .is_empty());
+    assert!(allowed(&scenario, &admin(MANAGEMENT), "ec2:RunInstances"));
+}
+
+#[test]
+fn scps_do_not_apply_outside_the_organization() {
+    let scenario = scenario_with_organization();
+    assert!(allowed(&scenario, &admin(OUTSIDE), "ec2:RunInstances"));
+}
+
+#[test]
+fn scps_do_not_apply_to_service_linked_roles() {
+    let scenario = scenario_with_organization();
+    let slr_session =
+        format!("arn:aws:sts::{SANDBOX}:assumed-role/AWSServiceRoleForSupport/support");
+    assert!(allowed(&scenario, &slr_session, "ec2:DescribeInstances"));
+    let admin_session = format!("arn:aws:sts::{SANDBOX}:assumed-role/Admin/ops");
+    assert!(!allowed(&scenario, &admin_session, "ec2:DescribeInstances"));
+}
+
+#[test]
+fn undefined_scp_is_an_error() {
+    let mut scenario = scenario_with_organization();
+    scenario
+        .organization
+        .as_mut()
+        .unwrap()
+        .root
+        .service_control_policies
+        .push("NoSuchPolicy".to_owned());
+    let err = scenario
+        .service_control_policies(&Principal::ARN(admin(WORKLOAD)))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "\"Root\" has service control policy \"NoSuchPolicy\" which is not defined in the scenario"
+    );
+}
+
+#[test]
+fn load_organization_from_scenario_file() {
+    let scenario = Scenario::from_json_file("example/scenario/organization.json".into()).unwrap();
+    let scps = scenario
+        .service_control_policies(&Principal::ARN(admin(WORKLOAD)))
+        .unwrap();
+    let names = scps
+        .iter()
+        .map(|level| level.iter().map(|(name, _)| *name).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            vec!["FullAWSAccess"],
+            vec!["FullAWSAccess", "DenyOutsideUsEast1"],
+            vec!["FullAWSAccess"]
+        ]
+    );
+}
+
+/// The workload account's queue is open to everyone by its resource policy, but an
+/// RCP on the Workloads OU denies access from outside the organization.
+fn scenario_with_data_perimeter() -> Scenario {
+    let mut scenario = scenario_with_organization();
+    let perimeter = Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Sid": "EnforceOrgIdentities",
+                "Effect": "Deny",
+                "Principal": "*",
+                "Action": ["sqs:*", "s3:*"],
+                "Resource": "*",
+                "Condition": {
+                    "StringNotEqualsIfExists": {
+                        "aws:PrincipalAccount": [MANAGEMENT, WORKLOAD, SANDBOX, UNATTACHED]
+                    }
+                }
+            }
+        ]
+    }))
+    .unwrap();
+    scenario
+        .resource_control_policies
+        .insert("DataPerimeter".to_owned(), perimeter);
+    let organization = scenario.organization.as_mut().unwrap();
+    organization.root.organizational_units[0]
+        .resource_control_policies
+        .push("DataPerimeter".to_owned());
+    organization
+        .root
+        .resource_control_policies
+        .push("DataPerimeter".to_owned());
+    scenario.add_resource_policy(
+        "OpenQueues",
+        Policy::from_json_value(json!({
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": "sqs:SendMessage",
+                    "Resource": "*"
+                }
+            ]
+        }))
+        .unwrap(),
+    );
+    scenario
+}
+
+fn send_message(principal_arn: &str, queue_account: &str) -> Request {
+    Request::new(
+        Principal::ARN(principal_arn.to_owned()),
+        "sqs:SendMessage",
+        &format!("arn:aws:sqs:us-east-1:{queue_account}:jobs"),
+    )
+}
+
+#[test]
+fn rcp_denies_access_from_outside_the_organization() {
+    let scenario = scenario_with_data_perimeter();
+    let outsider = send_message(&admin(OUTSIDE), WORKLOAD);
+    let names = scenario
+        .resource_control_policies(&outsider)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["DataPerimeter", "DataPerimeter"]);
+    assert_eq!(
+        scenario.eval(&outsider).unwrap().outcome,
+        Outcome::ExplicitDeny
+    );
+    assert!(scenario
+        .eval(&send_message(&admin(WORKLOAD), WORKLOAD))
+        .unwrap()
+        .is_allow());
+}
+
+#[test]
+fn rcps_apply_only_to_resources_in_member_accounts() {
+    let scenario = scenario_with_data_perimeter();
+    for queue_account in [MANAGEMENT, OUTSIDE] {
+        let request = send_message(&admin(OUTSIDE), queue_account);
+        assert!(scenario
+            .resource_control_policies(&request)
+            .unwrap()
+            .is_empty());
+        assert!(
+            scenario.eval(&request).unwrap().is_allow(),
+            "{queue_account}"
+        );
+    }
+}
+
+#[test]
+fn rcps_apply_to_s3_buckets_by_their_owning_account() {
+    let mut scenario = scenario_with_data_perimeter();
+    for (bucket, account_id) in [("workload-data", WORKLOAD), ("management-data", MANAGEMENT)] {
+        scenario.resources.push(Resource {
+            arn: vec![format!("arn:aws:s3:::{bucket}/*")],
+            account_id: account_id.to_owned(),
+        });
+    }
+    let get_object = |bucket: &str| {
+        Request::new(
+            Principal::ARN(admin(OUTSIDE)),
+            "s3:GetObject",
+            &format!("arn:aws:s3:::{bucket}/key"),
+        )
+    };
+    let request = get_object("workload-data");
+    let names = scenario
+        .resource_control_policies(&request)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["DataPerimeter", "DataPerimeter"]);
+    assert_eq!(
+        scenario.eval(&request).unwrap().outcome,
+        Outcome::ExplicitDeny
+    );
+    assert!(scenario
+        .resource_control_policies(&get_object("management-data"))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn rcps_without_allow_statements_do_not_deny() {
+    // There is no explicit allow in the RCPs, but AWS's implicit `RCPFullAWSAccess`
+    // allows everything that is not denied.
+    let scenario = scenario_with_data_perimeter();
+    assert!(scenario
+        .eval(&send_message(&admin(WORKLOAD), SANDBOX))
+        .unwrap()
+        .is_allow());
+}
+
+#[test]
+fn rcps_do_not_apply_to_service_linked_roles() {
+    let scenario = scenario_with_data_perimeter();
+    let request = send_message(
+        &format!("arn:aws:sts::{OUTSIDE}:assumed-role/AWSServiceRoleForSupport/support"),
+        WORKLOAD,
+    );
+    assert!(scenario
+        .resource_control_policies(&request)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn undefined_rcp_is_an_error() {
+    let mut scenario = scenario_with_data_perimeter();
+    scenario
+        .organization
+        .as_mut()
+        .unwrap()
+        .root
+        .organizational_units[0]
+        .accounts[0]
+        .resource_control_policies
+        .push("NoSuchPolicy".to_owned());
+    let err = scenario
+        .eval(&send_message(&admin(WORKLOAD), WORKLOAD))
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        format!("\"{WORKLOAD}\" has resource control policy \"NoSuchPolicy\" which is not defined in the scenario")
+    );
+}
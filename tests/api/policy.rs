@@ -3,12 +3,15 @@
 use std::fs::read_to_string;
 
 use eyre::Result;
-use iamthat::principal::Principal;
+use iamthat::action::ActionGlob;
+use iamthat::json::StringOrList;
+use iamthat::principal::{Principal, PrincipalMapEntry, PrincipalOrNot, Principals};
 use indoc::indoc;
 use serde_json::json;
 
 use iamthat::policy::*;
 use iamthat::request::Request;
+use iamthat::role::TrustPolicy;
 
 // TODO: Test more types of Action glob.
 
@@ -71,9 +74,20 @@ fn deserialize_single_strings_abbreviate_lists() {
         }
         "#})
     .unwrap();
-    assert_eq!(policy.statement[0].action, ["s3:ListBuckets"]);
-    assert_eq!(policy.statement[0].resource, ["*"]);
-    // TODO: Check NotPrincipal; doesn't seem right.
+    assert_eq!(
+        policy.statement[0].action,
+        ActionOrNotAction::Action(vec!["s3:ListBuckets".parse().unwrap()])
+    );
+    assert_eq!(
+        policy.statement[0].resource,
+        Some(ResourceOrNotResource::Resource(vec!["*".parse().unwrap()]))
+    );
+    assert_eq!(
+        policy.statement[0].principal,
+        Some(PrincipalOrNot::NotPrincipal(Principals::Map(vec![
+            PrincipalMapEntry::CanonicalUser(StringOrList::String("012345".to_owned()))
+        ])))
+    );
 }
 
 #[test]
@@ -90,10 +104,11 @@ fn action_star_matches_in_resource_policy() -> Result<()> {
             ]
         }
     "#})?;
-    let request = Request {
-        action: "s3:ListBuckets".to_owned(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:ListBuckets",
+        "*",
+    );
     assert!(policy.allows(&request));
     Ok(())
 }
@@ -112,23 +127,39 @@ fn action_glob_case_insensitive_in_resource_policy() -> Result<()> {
             ]
         }
     })?;
-    let request = Request {
-        action: "S3:lISTbUCKETS".to_owned(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "S3:lISTbUCKETS",
+        "*",
+    );
     assert!(policy.allows(&request));
     assert!(!policy.denies(&request));
     Ok(())
 }
 
+#[test]
+fn question_mark_in_action_glob_matches_one_character() -> Result<()> {
+    let glob: ActionGlob = "ec2:?escribe*".parse()?;
+    assert!(glob.matches("ec2:DescribeInstances"));
+    assert!(glob.matches("EC2:describeinstances"));
+    assert!(!glob.matches("ec2:escribeInstances"));
+    assert!(!glob.matches("ec2:XXDescribeInstances"));
+    let glob: ActionGlob = "s3:GetObjectV?rsion".parse()?;
+    assert!(glob.matches("s3:GetObjectVersion"));
+    assert!(!glob.matches("s3:GetObjectVrsion"));
+    assert_eq!(glob.to_string(), "s3:GetObjectV?rsion");
+    Ok(())
+}
+
 #[test]
 fn action_matches_action_glob_in_resource_policy() -> Result<()> {
     let json = read_to_string("example/resource_policy/s3_list.json")?;
     let policy: Policy = serde_json::from_str(&json)?;
-    let request = Request {
-        action: "s3:ListBuckets".to_owned(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:ListBuckets",
+        "arn:aws:s3:::production",
+    );
     assert!(policy.allows(&request));
     assert!(!policy.denies(&request));
     Ok(())
@@ -138,13 +169,274 @@ fn action_matches_action_glob_in_resource_policy() -> Result<()> {
 fn action_does_not_match_resource_policy() -> Result<()> {
     let json = read_to_string("example/resource_policy/s3_list.json")?;
     let policy: Policy = serde_json::from_str(&json)?;
-    let request = Request {
-        action: "s3:CreateBucket".into(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:CreateBucket",
+        "arn:aws:s3:::production",
+    );
     assert!(!policy.allows(&request));
     assert!(!policy.denies(&request));
     // No result on this specific policy; if it didn't match any policy
     // at all the result would be an implicit deny.
     Ok(())
 }
+
+#[test]
+fn resource_does_not_match_resource_policy() -> Result<()> {
+    let json = read_to_string("example/resource_policy/s3_list.json")?;
+    let policy: Policy = serde_json::from_str(&json)?;
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:ListBuckets",
+        "arn:aws:s3:::staging",
+    );
+    assert!(!policy.allows(&request));
+    assert!(!policy.denies(&request));
+    Ok(())
+}
+
+#[test]
+fn resource_glob_matches_objects_in_bucket() -> Result<()> {
+    let policy: Policy = serde_json::from_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Resource": "arn:aws:s3:::production/reports/*",
+                    "Action": "s3:GetObject"
+                }
+            ]
+        }
+    })?;
+    let mut request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::production/reports/2023/q1.csv",
+    );
+    assert!(policy.allows(&request));
+    request.resource = "arn:aws:s3:::production/secrets/key".to_owned();
+    assert!(!policy.allows(&request));
+    // Resource matching is case-sensitive, unlike action matching.
+    request.resource = "arn:aws:s3:::Production/reports/2023/q1.csv".to_owned();
+    assert!(!policy.allows(&request));
+    Ok(())
+}
+
+#[test]
+fn request_without_resource_only_matches_resource_star() -> Result<()> {
+    let policy: Policy = serde_json::from_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Resource": "arn:aws:s3:::*",
+                    "Action": "s3:ListAllMyBuckets"
+                }
+            ]
+        }
+    })?;
+    let request: Request = serde_json::from_value(json! {
+        {
+            "Action": "s3:ListAllMyBuckets",
+            "Principal": { "ARN": "arn:aws:iam::111122223333:user/mateo" }
+        }
+    })?;
+    assert_eq!(request.resource, "*");
+    assert!(!policy.allows(&request));
+    Ok(())
+}
+
+#[test]
+fn action_and_not_action_are_exclusive() {
+    let err = serde_json::from_value::<Policy>(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Resource": "*",
+                    "Action": "s3:*",
+                    "NotAction": "iam:*"
+                }
+            ]
+        }
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("`Action` and `NotAction` may not both be present"),
+        "{err}"
+    );
+}
+
+#[test]
+fn action_or_not_action_is_required() {
+    let err = serde_json::from_value::<Policy>(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Resource": "*"
+                }
+            ]
+        }
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("one of `Action` or `NotAction` must be present"),
+        "{err}"
+    );
+}
+
+#[test]
+fn resource_and_not_resource_are_exclusive() {
+    let err = serde_json::from_value::<Policy>(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Resource": "*",
+                    "NotResource": "arn:aws:s3:::secrets/*",
+                    "Action": "s3:*"
+                }
+            ]
+        }
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("`Resource` and `NotResource` may not both be present"),
+        "{err}"
+    );
+}
+
+#[test]
+fn not_action_guardrail() -> Result<()> {
+    let policy: Policy = serde_json::from_value(json! {
+        {
+            "Statement": [
+                {
+                    "Sid": "DenyAllExceptIam",
+                    "Effect": "Deny",
+                    "NotAction": ["iam:*", "sts:GetCallerIdentity"],
+                    "Resource": "*"
+                }
+            ]
+        }
+    })?;
+    assert_eq!(
+        policy.statement[0].action,
+        ActionOrNotAction::NotAction(vec!["iam:*".parse()?, "sts:GetCallerIdentity".parse()?])
+    );
+    let mut request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:ListAllMyBuckets",
+        "*",
+    );
+    assert!(policy.denies(&request));
+    request.action = "iam:CreateUser".to_owned();
+    assert!(!policy.denies(&request));
+    request.action = "sts:GetCallerIdentity".to_owned();
+    assert!(!policy.denies(&request));
+    Ok(())
+}
+
+#[test]
+fn not_resource_excludes_matching_resources() -> Result<()> {
+    let policy: Policy = serde_json::from_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Action": "s3:GetObject",
+                    "NotResource": "arn:aws:s3:::production/secrets/*"
+                }
+            ]
+        }
+    })?;
+    let mut request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::production/reports/q1.csv",
+    );
+    assert!(policy.allows(&request));
+    request.resource = "arn:aws:s3:::production/secrets/key".to_owned();
+    assert!(!policy.allows(&request));
+    Ok(())
+}
+
+#[test]
+fn not_action_round_trips_through_json() -> Result<()> {
+    let json = json! {
+        {
+            "Version": "2012-10-17",
+            "Statement": [
+                {
+                    "Effect": "Deny",
+                    "NotAction": ["iam:*"],
+                    "NotResource": ["arn:aws:iam::111122223333:role/admin"]
+                }
+            ]
+        }
+    };
+    let policy: Policy = serde_json::from_value(json.clone())?;
+    assert_eq!(serde_json::to_value(&policy)?, json);
+    Ok(())
+}
+
+#[test]
+fn statement_without_resource_is_an_error() {
+    let err = serde_json::from_value::<Policy>(json! {
+        {
+            "Statement": [ { "Effect": "Allow", "Action": "s3:*" } ]
+        }
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("one of `Resource` or `NotResource` must be present"),
+        "{err}"
+    );
+}
+
+#[test]
+fn trust_policy_statement_applies_to_any_resource() -> Result<()> {
+    let policy: TrustPolicy = serde_json::from_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "111122223333" },
+                    "Action": "sts:AssumeRole"
+                }
+            ]
+        }
+    })?;
+    assert_eq!(policy.0.statement[0].resource, None);
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "sts:AssumeRole",
+        "arn:aws:iam::111122223333:role/Deploy",
+    );
+    assert!(policy.0.allows(&request));
+    Ok(())
+}
+
+#[test]
+fn trust_policy_may_not_have_a_resource() {
+    let err = serde_json::from_value::<TrustPolicy>(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": "111122223333" },
+                    "Action": "sts:AssumeRole",
+                    "Resource": "*"
+                }
+            ]
+        }
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("unknown field `Resource`"), "{err}");
+}
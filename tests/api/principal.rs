@@ -0,0 +1,258 @@
+// Copyright 2023 Martin Pool
+
+use serde_json::json;
+
+use iamthat::json::FromJson;
+use iamthat::json::StringOrList;
+use iamthat::policy::Policy;
+use iamthat::principal::{
+    aws_principal_matches, Principal, PrincipalMapEntry, PrincipalOrNot, Principals,
+};
+use iamthat::Request;
+
+const MATEO: &str = "arn:aws:iam::111122223333:user/mateo";
+const SESSION: &str = "arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234";
+
+fn arn(arn: &str) -> Principal {
+    Principal::ARN(arn.to_owned())
+}
+
+fn service(name: &str) -> Principal {
+    Principal::Service(name.to_owned())
+}
+
+#[test]
+fn star_matches_everyone() {
+    assert!(aws_principal_matches("*", &arn(MATEO)));
+    assert!(aws_principal_matches("*", &arn(SESSION)));
+    assert!(aws_principal_matches(
+        "*",
+        &service("cloudtrail.amazonaws.com")
+    ));
+}
+
+#[test]
+fn account_id_matches_principals_in_that_account() {
+    for account in ["111122223333", "arn:aws:iam::111122223333:root"] {
+        assert!(aws_principal_matches(account, &arn(MATEO)), "{account}");
+        assert!(aws_principal_matches(account, &arn(SESSION)), "{account}");
+        assert!(
+            aws_principal_matches(account, &arn("arn:aws:iam::111122223333:root")),
+            "{account}"
+        );
+        assert!(
+            !aws_principal_matches(account, &arn("arn:aws:iam::444455556666:user/mateo")),
+            "{account}"
+        );
+        assert!(
+            !aws_principal_matches(account, &service("cloudtrail.amazonaws.com")),
+            "{account}"
+        );
+    }
+}
+
+#[test]
+fn user_arn_matches_exactly() {
+    assert!(aws_principal_matches(MATEO, &arn(MATEO)));
+    assert!(!aws_principal_matches(
+        MATEO,
+        &arn("arn:aws:iam::111122223333:user/zhang")
+    ));
+    assert!(!aws_principal_matches(
+        MATEO,
+        &arn("arn:aws:iam::444455556666:user/mateo")
+    ));
+    assert!(!aws_principal_matches(
+        "arn:aws:iam::111122223333:user/*",
+        &arn(MATEO)
+    ));
+}
+
+#[test]
+fn role_arn_matches_sessions_of_the_role() {
+    for role in [
+        "arn:aws:iam::111122223333:role/Deploy",
+        "arn:aws:iam::111122223333:role/ci/Deploy",
+    ] {
+        assert!(aws_principal_matches(role, &arn(SESSION)), "{role}");
+        assert!(
+            !aws_principal_matches(
+                role,
+                &arn("arn:aws:sts::111122223333:assumed-role/DeployAdmin/ci-1234")
+            ),
+            "{role}"
+        );
+        assert!(
+            !aws_principal_matches(
+                role,
+                &arn("arn:aws:sts::444455556666:assumed-role/Deploy/ci-1234")
+            ),
+            "{role}"
+        );
+    }
+}
+
+#[test]
+fn session_arn_matches_only_that_session() {
+    assert!(aws_principal_matches(SESSION, &arn(SESSION)));
+    assert!(!aws_principal_matches(
+        SESSION,
+        &arn("arn:aws:sts::111122223333:assumed-role/Deploy/ci-5678")
+    ));
+}
+
+#[test]
+fn service_principals() {
+    let principal = PrincipalOrNot::Principal(Principals::Map(vec![PrincipalMapEntry::Service(
+        StringOrList::String("cloudtrail.amazonaws.com".to_owned()),
+    )]));
+    assert!(principal.matches(&service("cloudtrail.amazonaws.com")));
+    assert!(!principal.matches(&service("config.amazonaws.com")));
+    assert!(!principal.matches(&arn(MATEO)));
+}
+
+#[test]
+fn not_principal_matches_everyone_else() {
+    let not_principal =
+        PrincipalOrNot::NotPrincipal(Principals::Map(vec![PrincipalMapEntry::AWS(
+            StringOrList::List(vec![MATEO.to_owned()]),
+        )]));
+    assert!(!not_principal.matches(&arn(MATEO)));
+    assert!(not_principal.matches(&arn("arn:aws:iam::111122223333:user/zhang")));
+    assert!(not_principal.matches(&service("cloudtrail.amazonaws.com")));
+}
+
+#[test]
+fn bucket_policy_grants_only_to_named_principal() {
+    let policy = Policy::from_json_value(json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": { "AWS": MATEO },
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::mybucket/*"
+            }
+        ]
+    }))
+    .unwrap();
+    let request = |principal: Principal| {
+        Request::new(principal, "s3:GetObject", "arn:aws:s3:::mybucket/mykey")
+    };
+    assert!(policy.allows(&request(arn(MATEO))));
+    assert!(!policy.allows(&request(arn("arn:aws:iam::111122223333:user/zhang"))));
+    assert!(!policy.allows(&request(service("cloudtrail.amazonaws.com"))));
+}
+
+/// Principals in the forms written by AWS serialize back exactly the same way.
+#[test]
+fn principal_json_round_trips() {
+    for principal in [
+        json!({ "Principal": "*" }),
+        json!({ "Principal": { "AWS": "*" } }),
+        json!({ "Principal": { "AWS": "111122223333" } }),
+        json!({ "Principal": { "AWS": [MATEO] } }),
+        json!({
+            "Principal": {
+                "Service": ["ec2.amazonaws.com", "lambda.amazonaws.com"],
+                "AWS": ["arn:aws:iam::111122223333:root", MATEO]
+            }
+        }),
+        json!({ "NotPrincipal": { "CanonicalUser": "79a59df900b949e55d96a1e698fbacedfd6e09d98eacf8f8d5218e7cd47ef2be" } }),
+        json!({ "Principal": { "Federated": "cognito-identity.amazonaws.com" } }),
+    ] {
+        let mut statement = json!({
+            "Effect": "Allow",
+            "Action": ["s3:GetObject"],
+            "Resource": ["arn:aws:s3:::mybucket/*"]
+        });
+        let (key, value) = principal.as_object().unwrap().iter().next().unwrap();
+        statement[key] = value.clone();
+        let policy = json!({ "Version": "2012-10-17", "Statement": [statement] });
+        let parsed = Policy::from_json_value(policy.clone()).unwrap();
+        assert!(parsed.statement[0].principal.is_some());
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), policy);
+    }
+}
+
+#[test]
+fn principal_map_order_is_preserved() {
+    let json = r#"{"Service":"ec2.amazonaws.com","AWS":["111122223333"]}"#;
+    let principals: Principals = serde_json::from_str(json).unwrap();
+    assert_eq!(serde_json::to_string(&principals).unwrap(), json);
+}
+
+#[test]
+fn principal_star_string_is_parsed() {
+    let policy = Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": "*",
+                "Action": "s3:GetObject",
+                "Resource": "*"
+            }
+        ]
+    }))
+    .unwrap();
+    assert_eq!(
+        policy.statement[0].principal,
+        Some(PrincipalOrNot::Principal(Principals::Star))
+    );
+}
+
+#[test]
+fn invalid_principals_are_errors() {
+    for (principal, expected) in [
+        (json!("mateo"), "invalid value: string \"mateo\""),
+        (json!({ "Person": "mateo" }), "unknown field `Person`"),
+        (json!(["*"]), "invalid type: sequence"),
+        (json!({ "AWS": 12 }), "invalid type: integer `12`"),
+    ] {
+        let err = Policy::from_json_value(json!({
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": principal,
+                    "Action": "s3:GetObject",
+                    "Resource": "*"
+                }
+            ]
+        }))
+        .unwrap_err();
+        let err = format!("{err:#}");
+        assert!(err.contains(expected), "{err}");
+    }
+}
+
+#[test]
+fn principal_and_not_principal_are_exclusive() {
+    let err = serde_json::from_value::<Policy>(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": "*",
+                "NotPrincipal": { "AWS": MATEO },
+                "Action": "s3:GetObject",
+                "Resource": "*"
+            }
+        ]
+    }))
+    .unwrap_err()
+    .to_string();
+    assert!(
+        err.contains("`Principal` and `NotPrincipal` may not both be present"),
+        "{err}"
+    );
+}
+
+#[test]
+fn assumed_role_parts() {
+    let session = arn(SESSION).assumed_role().unwrap();
+    assert_eq!(session.account_id, "111122223333");
+    assert_eq!(session.role_name, "Deploy");
+    assert_eq!(session.session_name, "ci-1234");
+    assert_eq!(session.arn(), SESSION);
+    assert_eq!(arn(MATEO).assumed_role(), None);
+    assert_eq!(service("ec2.amazonaws.com").assumed_role(), None);
+}
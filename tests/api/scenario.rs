@@ -7,6 +7,8 @@ use tracing_test::traced_test;
 use iamthat::json::FromJson;
 use iamthat::policy::Policy;
 use iamthat::scenario::Scenario;
+use iamthat::tag::Tag;
+use iamthat::user::User;
 use iamthat::Request;
 
 #[test]
@@ -20,13 +22,15 @@ fn deny_overrides_allow() {
             "Statement": [
                 {
                     "Effect": "Allow",
-                    "Resource": "s3:*",
-                    "Action": "arn:aws:s3:::mybucket/*"
+                    "Principal": "*",
+                    "Action": "s3:*",
+                    "Resource": "arn:aws:s3:::mybucket/*"
                 },
                 {
                     "Effect": "Deny",
-                    "Resource": "s3:*",
-                    "Action": "arn:aws:s3:::mybucket/*"
+                    "Principal": "*",
+                    "Action": "s3:*",
+                    "Resource": "arn:aws:s3:::mybucket/*"
                 }
             ]
         }
@@ -34,10 +38,11 @@ fn deny_overrides_allow() {
         .unwrap(),
     );
 
-    let request = Request {
-        action: "s3:GetObject".to_string(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::mybucket/mykey",
+    );
 
     assert!(scenario.eval(&request).unwrap().is_deny());
 }
@@ -47,10 +52,11 @@ fn deny_overrides_allow() {
 fn scenario_with_no_policies_causes_implicit_deny() -> eyre::Result<()> {
     let scenario = Scenario::new();
 
-    let request = Request {
-        action: "aws-pca:IssueCertificate".to_string(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "aws-pca:IssueCertificate",
+        "arn:aws:s3:::mybucket/mykey",
+    );
 
     assert!(scenario.eval(&request).unwrap().is_deny());
 
@@ -69,20 +75,487 @@ fn lack_of_match_is_implicit_deny() -> eyre::Result<()> {
             "Statement": [
                 {
                     "Effect": "Allow",
-                    "Resource": "s3:*",
-                    "Action": "arn:aws:s3:::mybucket/*"
+                    "Principal": "*",
+                    "Action": "s3:*",
+                    "Resource": "arn:aws:s3:::mybucket/*"
                 }
             ]
         }
         })?,
     );
 
-    let request = Request {
-        action: "aws-pca:IssueCertificate".to_string(),
-        principal: Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
-    };
+    let request = Request::new(
+        Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+        "aws-pca:IssueCertificate",
+        "arn:aws:s3:::mybucket/mykey",
+    );
 
     assert!(scenario.eval(&request).unwrap().is_deny());
 
     Ok(())
 }
+
+fn scenario_with_mateo() -> Scenario {
+    let mut scenario = Scenario::new();
+    scenario.users.push(User {
+        user_name: "mateo".to_owned(),
+        user_id: Some("AIDAEXAMPLEMATEO".to_owned()),
+        path: "/eng/".to_owned(),
+        tags: vec![Tag {
+            key: "team".to_owned(),
+            value: "dev".to_owned(),
+        }],
+        ..Default::default()
+    });
+    scenario
+}
+
+fn request_from(principal_arn: &str) -> Request {
+    Request::new(
+        Principal::ARN(principal_arn.to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::mybucket/mykey",
+    )
+}
+
+#[test]
+fn request_context_is_filled_in_from_user() {
+    let scenario = scenario_with_mateo();
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    assert_eq!(
+        scenario.find_user(&request.principal).unwrap().user_name,
+        "mateo"
+    );
+    let context = scenario.request_context(&request);
+    assert_eq!(context.get_one("aws:username"), Some("mateo"));
+    assert_eq!(context.get_one("aws:userid"), Some("AIDAEXAMPLEMATEO"));
+    assert_eq!(
+        context.get_one("aws:PrincipalAccount"),
+        Some("111122223333")
+    );
+    assert_eq!(
+        context.get_one("aws:PrincipalArn"),
+        Some("arn:aws:iam::111122223333:user/eng/mateo")
+    );
+    assert_eq!(context.get_one("aws:PrincipalType"), Some("User"));
+    assert_eq!(context.principal_tag("team"), Some("dev"));
+}
+
+#[test]
+fn request_context_keys_in_request_are_not_replaced() {
+    let scenario = scenario_with_mateo();
+    let mut request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    request.context.set_principal_tag("team", "ops");
+    let context = scenario.request_context(&request);
+    assert_eq!(context.principal_tag("team"), Some("ops"));
+    assert_eq!(context.get_one("aws:username"), Some("mateo"));
+}
+
+#[test]
+fn request_context_for_principal_not_in_scenario() {
+    let scenario = scenario_with_mateo();
+    // Not the same user: the path is different.
+    let request = request_from("arn:aws:iam::111122223333:user/mateo");
+    assert!(scenario.find_user(&request.principal).is_none());
+    let context = scenario.request_context(&request);
+    assert_eq!(
+        context.get_one("aws:PrincipalAccount"),
+        Some("111122223333")
+    );
+    assert_eq!(context.get_one("aws:PrincipalType"), Some("User"));
+    assert_eq!(context.get("aws:username"), None);
+    assert_eq!(context.principal_tag("team"), None);
+}
+
+#[test]
+fn principal_tag_condition_uses_user_tags() {
+    let mut scenario = scenario_with_mateo();
+    scenario.add_resource_policy(
+        "allow_dev_team",
+        Policy::from_json_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": "s3:GetObject",
+                    "Resource": "arn:aws:s3:::mybucket/*",
+                    "Condition": {
+                        "StringEquals": { "aws:PrincipalTag/team": "dev" }
+                    }
+                }
+            ]
+        }
+        })
+        .unwrap(),
+    );
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    assert!(scenario.eval(&request).unwrap().is_allow());
+    let mut request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    request.context.set_principal_tag("team", "ops");
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+fn allow_get_object_policy() -> Policy {
+    Policy::from_json_value(json! {
+    {
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::mybucket/*"
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+#[test]
+fn attached_identity_policy_applies_only_to_its_user() {
+    let mut scenario = scenario_with_mateo();
+    scenario.add_named_policy("AllowGetObject", allow_get_object_policy());
+    scenario.users[0]
+        .attached_policies
+        .push("AllowGetObject".to_owned());
+    scenario.users.push(User {
+        user_name: "zhang".to_owned(),
+        ..Default::default()
+    });
+
+    let mateo = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    let (names, _): (Vec<&str>, Vec<&Policy>) = scenario
+        .identity_policies(&mateo.principal)
+        .unwrap()
+        .into_iter()
+        .unzip();
+    assert_eq!(names, ["AllowGetObject"]);
+    assert!(scenario.eval(&mateo).unwrap().is_allow());
+
+    let zhang = request_from("arn:aws:iam::111122223333:user/zhang");
+    assert!(scenario
+        .identity_policies(&zhang.principal)
+        .unwrap()
+        .is_empty());
+    assert!(scenario.eval(&zhang).unwrap().is_deny());
+
+    let stranger = request_from("arn:aws:iam::444455556666:user/eng/stranger");
+    assert!(scenario.eval(&stranger).unwrap().is_deny());
+}
+
+#[test]
+fn unattached_named_policy_does_not_apply() {
+    let mut scenario = scenario_with_mateo();
+    scenario.add_named_policy("AllowGetObject", allow_get_object_policy());
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+#[test]
+fn identity_policy_deny_overrides_resource_policy_allow() {
+    let mut scenario = scenario_with_mateo();
+    scenario.add_resource_policy(
+        "AllowGetObject",
+        Policy::from_json_value(json! {
+        {
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": "*",
+                    "Action": "s3:GetObject",
+                    "Resource": "arn:aws:s3:::mybucket/*"
+                }
+            ]
+        }
+        })
+        .unwrap(),
+    );
+    scenario.add_named_policy(
+        "DenyS3",
+        Policy::from_json_value(json! {
+        {
+            "Statement": [
+                { "Effect": "Deny", "Action": "s3:*", "Resource": "*" }
+            ]
+        }
+        })
+        .unwrap(),
+    );
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    assert!(scenario.eval(&request).unwrap().is_allow());
+    scenario.users[0]
+        .attached_policies
+        .push("DenyS3".to_owned());
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+#[test]
+fn undefined_attached_policy_is_an_error() {
+    let mut scenario = scenario_with_mateo();
+    scenario.users[0]
+        .attached_policies
+        .push("NoSuchPolicy".to_owned());
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    let err = scenario.eval(&request).unwrap_err().to_string();
+    assert!(err.contains("\"NoSuchPolicy\""), "{err}");
+}
+
+#[test]
+fn inline_user_policies_are_identity_policies() {
+    let scenario = Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {},
+        "Users": [
+            {
+                "UserName": "mateo",
+                "InlinePolicies": {
+                    "AllowGetObject": {
+                        "Statement": [
+                            {
+                                "Effect": "Allow",
+                                "Action": "s3:GetObject",
+                                "Resource": "arn:aws:s3:::mybucket/*"
+                            }
+                        ]
+                    }
+                }
+            },
+            { "UserName": "zhang" }
+        ]
+    }
+    })
+    .unwrap();
+    let mateo = request_from("arn:aws:iam::111122223333:user/mateo");
+    assert!(scenario.eval(&mateo).unwrap().is_allow());
+    let zhang = request_from("arn:aws:iam::111122223333:user/zhang");
+    assert!(scenario.eval(&zhang).unwrap().is_deny());
+}
+
+#[test]
+fn load_inline_policies_relative_to_scenario() {
+    let scenario =
+        Scenario::from_json_file("example/scenario/s3_home_directory.json".into()).unwrap();
+    let zhang = scenario
+        .find_user(&Principal::ARN(
+            "arn:aws:iam::111122223333:user/zhang".to_owned(),
+        ))
+        .unwrap();
+    assert_eq!(
+        zhang.inline_policies["ReadShared"].statement[0]
+            .sid
+            .as_deref(),
+        Some("ReadSharedDirectory")
+    );
+}
+
+#[test]
+fn group_policies_apply_to_members() {
+    let scenario = Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "AllowGetObject": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::mybucket/*"
+                    }
+                ]
+            }
+        },
+        "Groups": [
+            {
+                "GroupName": "Readers",
+                "AttachedPolicies": ["AllowGetObject"]
+            },
+            {
+                "GroupName": "NoDeletes",
+                "InlinePolicies": {
+                    "DenyDelete": {
+                        "Statement": [
+                            { "Effect": "Deny", "Action": "s3:Delete*", "Resource": "*" }
+                        ]
+                    }
+                }
+            }
+        ],
+        "Users": [
+            {
+                "UserName": "mateo",
+                "Groups": ["Readers", "NoDeletes"],
+                "InlinePolicies": {
+                    "AllowS3": {
+                        "Statement": [
+                            { "Effect": "Allow", "Action": "s3:*", "Resource": "*" }
+                        ]
+                    }
+                }
+            },
+            { "UserName": "zhang", "Groups": ["Readers"] },
+            { "UserName": "ana" }
+        ]
+    }
+    })
+    .unwrap();
+    let mateo = request_from("arn:aws:iam::111122223333:user/mateo");
+    let (names, _): (Vec<&str>, Vec<&Policy>) = scenario
+        .identity_policies(&mateo.principal)
+        .unwrap()
+        .into_iter()
+        .unzip();
+    assert_eq!(names, ["AllowS3", "AllowGetObject", "DenyDelete"]);
+    assert!(scenario.eval(&mateo).unwrap().is_allow());
+    let delete = Request {
+        action: "s3:DeleteObject".to_owned(),
+        ..mateo.clone()
+    };
+    assert!(scenario.eval(&delete).unwrap().is_deny());
+
+    let zhang = request_from("arn:aws:iam::111122223333:user/zhang");
+    assert!(scenario.eval(&zhang).unwrap().is_allow());
+    let ana = request_from("arn:aws:iam::111122223333:user/ana");
+    assert!(scenario.eval(&ana).unwrap().is_deny());
+}
+
+#[test]
+fn undefined_group_is_an_error() {
+    let mut scenario = scenario_with_mateo();
+    scenario.users[0].groups.push("NoSuchGroup".to_owned());
+    let request = request_from("arn:aws:iam::111122223333:user/eng/mateo");
+    let err = scenario.eval(&request).unwrap_err().to_string();
+    assert!(err.contains("\"NoSuchGroup\""), "{err}");
+}
+
+fn scenario_with_deploy_role() -> Scenario {
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "PutArtifacts": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": "s3:PutObject",
+                        "Resource": "arn:aws:s3:::artifacts/*"
+                    }
+                ]
+            }
+        },
+        "Users": [],
+        "Roles": [
+            {
+                "RoleName": "Deploy",
+                "RoleId": "AROAEXAMPLEDEPLOY",
+                "Path": "/ci/",
+                "AssumeRolePolicyDocument": {
+                    "Statement": [
+                        {
+                            "Effect": "Allow",
+                            "Principal": { "AWS": "111122223333" },
+                            "Action": "sts:AssumeRole"
+                        }
+                    ]
+                },
+                "AttachedPolicies": ["PutArtifacts"],
+                "Tags": [ { "Key": "team", "Value": "platform" } ]
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+#[test]
+fn role_session_uses_role_policies() {
+    let scenario = scenario_with_deploy_role();
+    assert_eq!(scenario.roles[0].max_session_duration, 3600);
+    let mut request = request_from("arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234");
+    request.action = "s3:PutObject".to_owned();
+    request.resource = "arn:aws:s3:::artifacts/app.tar.gz".to_owned();
+    assert_eq!(
+        scenario
+            .find_role_for_session(&request.principal)
+            .unwrap()
+            .role_name,
+        "Deploy"
+    );
+    assert!(scenario.eval(&request).unwrap().is_allow());
+
+    request.resource = "arn:aws:s3:::production/app.tar.gz".to_owned();
+    assert!(scenario.eval(&request).unwrap().is_deny());
+
+    let mut other_role = request_from("arn:aws:sts::111122223333:assumed-role/Other/ci-1234");
+    other_role.action = "s3:PutObject".to_owned();
+    other_role.resource = "arn:aws:s3:::artifacts/app.tar.gz".to_owned();
+    assert!(scenario
+        .find_role_for_session(&other_role.principal)
+        .is_none());
+    assert!(scenario.eval(&other_role).unwrap().is_deny());
+}
+
+#[test]
+fn request_context_for_role_session() {
+    let scenario = scenario_with_deploy_role();
+    let request = request_from("arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234");
+    let context = scenario.request_context(&request);
+    assert_eq!(
+        context.get_one("aws:PrincipalArn"),
+        Some("arn:aws:iam::111122223333:role/ci/Deploy")
+    );
+    assert_eq!(context.get_one("aws:PrincipalType"), Some("AssumedRole"));
+    assert_eq!(
+        context.get_one("aws:userid"),
+        Some("AROAEXAMPLEDEPLOY:ci-1234")
+    );
+    assert_eq!(context.principal_tag("team"), Some("platform"));
+    assert_eq!(context.get_one("aws:username"), None);
+}
+
+#[test]
+fn load_role_from_scenario_file() {
+    let scenario = Scenario::from_json_file("example/scenario/deploy_role.json".into()).unwrap();
+    let role = scenario.find_role("Deploy").unwrap();
+    assert_eq!(
+        role.arn("111122223333"),
+        "arn:aws:iam::111122223333:role/ci/Deploy"
+    );
+    assert_eq!(role.max_session_duration, 7200);
+    assert_eq!(
+        role.assume_role_policy_document.0.statement[0]
+            .sid
+            .as_deref(),
+        Some("TrustMateo")
+    );
+}
+
+#[test]
+fn resource_policy_without_principal_is_a_load_error() {
+    let err = Scenario::from_json_value(json!({
+        "NamedPolicies": {},
+        "ResourcePolicies": {
+            "Open": {
+                "Statement": [
+                    { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" }
+                ]
+            }
+        },
+        "Users": []
+    }))
+    .unwrap_err();
+    let iamthat::Error::Parse { pointer, error, .. } = &err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(pointer, "/ResourcePolicies/Open");
+    assert!(
+        error
+            .to_string()
+            .starts_with("statement 0 of a resource policy has no `Principal` or `NotPrincipal`"),
+        "{err}"
+    );
+}
+
+#[test]
+#[should_panic(expected = "Resource policy AllowGetObject: statement 0 of a resource policy")]
+fn adding_resource_policy_without_principal_panics() {
+    let mut scenario = Scenario::new();
+    scenario.add_resource_policy("AllowGetObject", allow_get_object_policy());
+}
@@ -0,0 +1,94 @@
+// Copyright 2023 Martin Pool
+
+//! Check the example policies against the generated JSON schema.
+
+use std::fs::read_to_string;
+
+use glob::glob;
+use jsonschema::Validator;
+use serde_json::{json, Value};
+
+fn policy_validator() -> Validator {
+    let schema: Value =
+        serde_json::from_str(&read_to_string("schema/policy.json").unwrap()).unwrap();
+    jsonschema::validator_for(&schema).unwrap()
+}
+
+/// Return a description of each place where `value` doesn't match the schema.
+fn validate(validator: &Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|err| format!("{}: {err}", err.instance_path))
+        .collect()
+}
+
+#[test]
+fn example_policies_match_the_policy_schema() {
+    let validator = policy_validator();
+    let mut count = 0;
+    for dir in [
+        "identity_policy",
+        "resource_policy",
+        "resource_control_policy",
+        "service_control_policy",
+    ] {
+        for path in glob(&format!("example/{dir}/*.json")).unwrap() {
+            let path = path.unwrap();
+            let policy: Value = serde_json::from_str(&read_to_string(&path).unwrap()).unwrap();
+            let errors = validate(&validator, &policy);
+            assert!(errors.is_empty(), "{path:?}: {errors:#?}");
+            count += 1;
+        }
+    }
+    assert!(count > 10, "only found {count} example policies");
+}
+
+#[test]
+fn policy_schema_requires_exactly_one_of_each_element() {
+    let validator = policy_validator();
+    let statement = json!({
+        "Effect": "Allow",
+        "Principal": "*",
+        "Action": "s3:GetObject",
+        "Resource": ["arn:aws:s3:::bucket/*"],
+    });
+    let check = |change: &dyn Fn(&mut Value)| {
+        let mut statement = statement.clone();
+        change(&mut statement);
+        let policy = json!({ "Version": "2012-10-17", "Statement": [statement] });
+        validate(&validator, &policy)
+    };
+    assert_eq!(check(&|_| ()), Vec::<String>::new());
+    assert_eq!(
+        check(&|s| {
+            s.as_object_mut().unwrap().remove("Principal");
+        }),
+        Vec::<String>::new(),
+        "Principal is optional"
+    );
+    assert_ne!(
+        check(&|s| s["NotPrincipal"] = json!("*")),
+        Vec::<String>::new()
+    );
+    assert_ne!(
+        check(&|s| s["NotAction"] = json!("s3:*")),
+        Vec::<String>::new()
+    );
+    assert_ne!(
+        check(&|s| {
+            s.as_object_mut().unwrap().remove("Action");
+        }),
+        Vec::<String>::new()
+    );
+    assert_ne!(
+        check(&|s| s["NotResource"] = json!("*")),
+        Vec::<String>::new()
+    );
+    assert_ne!(
+        check(&|s| {
+            s.as_object_mut().unwrap().remove("Resource");
+        }),
+        Vec::<String>::new()
+    );
+    assert_ne!(check(&|s| s["Color"] = json!("pink")), Vec::<String>::new());
+}
@@ -0,0 +1,305 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for session policies passed when assuming a role.
+
+use serde_json::json;
+
+use iamthat::assume::{AssumeRoleRequest, Session};
+use iamthat::decision::Outcome;
+use iamthat::json::FromJson;
+use iamthat::policy::{Policy, SessionPolicy};
+use iamthat::principal::Principal;
+use iamthat::scenario::{Scenario, INLINE_SESSION_POLICY};
+use iamthat::Request;
+
+const SESSION: &str = "arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234";
+const READ_ONLY_ARN: &str = "arn:aws:iam::111122223333:policy/ci/ReadOnly";
+
+fn scenario_with_deploy_role() -> Scenario {
+    Scenario::from_json_value(json! {
+    {
+        "NamedPolicies": {
+            "S3Access": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Action": ["s3:GetObject", "s3:PutObject"],
+                        "Resource": "arn:aws:s3:::artifacts/*"
+                    }
+                ]
+            },
+            "AssumeDeploy": {
+                "Statement": [
+                    { "Effect": "Allow", "Action": "sts:AssumeRole", "Resource": "*" }
+                ]
+            },
+            "ReadOnly": {
+                "Statement": [
+                    { "Effect": "Allow", "Action": "s3:Get*", "Resource": "*" }
+                ]
+            }
+        },
+        "ResourcePolicies": {
+            "Reports": {
+                "Statement": [
+                    {
+                        "Effect": "Allow",
+                        "Principal": { "AWS": SESSION },
+                        "Action": "s3:PutObject",
+                        "Resource": "arn:aws:s3:::reports/*"
+                    }
+                ]
+            }
+        },
+        "Users": [
+            { "UserName": "mateo", "AttachedPolicies": ["AssumeDeploy"] }
+        ],
+        "Roles": [
+            {
+                "RoleName": "Deploy",
+                "AssumeRolePolicyDocument": {
+                    "Statement": [
+                        {
+                            "Effect": "Allow",
+                            "Principal": { "AWS": "arn:aws:iam::111122223333:user/mateo" },
+                            "Action": "sts:AssumeRole"
+                        }
+                    ]
+                },
+                "AttachedPolicies": ["S3Access"]
+            }
+        ]
+    }
+    })
+    .unwrap()
+}
+
+fn put_docs_only() -> Policy {
+    Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": "s3:PutObject",
+                "Resource": "arn:aws:s3:::artifacts/docs/*"
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn inline_session_policy_limits_role_permissions() {
+    let scenario = scenario_with_deploy_role();
+    let allowed = |action: &str, resource: &str| {
+        scenario
+            .eval(&Request {
+                session_policies: vec![SessionPolicy::Inline(put_docs_only())],
+                ..Request::new(Principal::ARN(SESSION.to_owned()), action, resource)
+            })
+            .unwrap()
+            .is_allow()
+    };
+    assert!(allowed(
+        "s3:PutObject",
+        "arn:aws:s3:::artifacts/docs/index.html"
+    ));
+    assert!(!allowed(
+        "s3:PutObject",
+        "arn:aws:s3:::artifacts/app.tar.gz"
+    ));
+    // Allowed by the session policy, but not by the role.
+    assert!(!allowed(
+        "s3:GetObject",
+        "arn:aws:s3:::artifacts/docs/index.html"
+    ));
+}
+
+#[test]
+fn managed_session_policy_by_arn() {
+    let scenario = scenario_with_deploy_role();
+    let session_policies = vec![SessionPolicy::Arn(READ_ONLY_ARN.to_owned())];
+    let get = Request {
+        session_policies: session_policies.clone(),
+        ..Request::new(Principal::ARN(SESSION.to_owned()), "s3:GetObject", "*")
+    };
+    let names = scenario
+        .session_policies(&get)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, [READ_ONLY_ARN]);
+    let allowed = |action: &str| {
+        scenario
+            .eval(&Request {
+                session_policies: session_policies.clone(),
+                ..Request::new(
+                    Principal::ARN(SESSION.to_owned()),
+                    action,
+                    "arn:aws:s3:::artifacts/app.tar.gz",
+                )
+            })
+            .unwrap()
+            .is_allow()
+    };
+    assert!(allowed("s3:GetObject"));
+    assert!(!allowed("s3:PutObject"));
+}
+
+#[test]
+fn multiple_session_policies_each_allow() {
+    let scenario = scenario_with_deploy_role();
+    let session_policies = vec![
+        SessionPolicy::Arn(READ_ONLY_ARN.to_owned()),
+        SessionPolicy::Inline(put_docs_only()),
+    ];
+    let get = Request {
+        session_policies: session_policies.clone(),
+        ..Request::new(Principal::ARN(SESSION.to_owned()), "s3:GetObject", "*")
+    };
+    let names = scenario
+        .session_policies(&get)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, [READ_ONLY_ARN, INLINE_SESSION_POLICY]);
+    for (action, resource) in [
+        ("s3:GetObject", "arn:aws:s3:::artifacts/app.tar.gz"),
+        ("s3:PutObject", "arn:aws:s3:::artifacts/docs/index.html"),
+    ] {
+        assert!(scenario
+            .eval(&Request {
+                session_policies: session_policies.clone(),
+                ..Request::new(Principal::ARN(SESSION.to_owned()), action, resource)
+            })
+            .unwrap()
+            .is_allow());
+    }
+}
+
+#[test]
+fn session_policy_deny_applies() {
+    let scenario = scenario_with_deploy_role();
+    let deny_put = Policy::from_json_value(json!({
+        "Statement": [
+            { "Effect": "Allow", "Action": "*", "Resource": "*" },
+            { "Effect": "Deny", "Action": "s3:PutObject", "Resource": "*" }
+        ]
+    }))
+    .unwrap();
+    let request = Request {
+        session_policies: vec![SessionPolicy::Inline(deny_put)],
+        ..Request::new(
+            Principal::ARN(SESSION.to_owned()),
+            "s3:PutObject",
+            "arn:aws:s3:::reports/q3.csv",
+        )
+    };
+    assert!(scenario.eval(&request).unwrap().is_deny());
+}
+
+#[test]
+fn resource_policy_naming_session_is_not_limited_by_session_policy() {
+    let scenario = scenario_with_deploy_role();
+    let request = Request {
+        session_policies: vec![SessionPolicy::Inline(put_docs_only())],
+        ..Request::new(
+            Principal::ARN(SESSION.to_owned()),
+            "s3:PutObject",
+            "arn:aws:s3:::reports/q3.csv",
+        )
+    };
+    assert!(scenario.eval(&request).unwrap().is_allow());
+}
+
+#[test]
+fn resource_policy_naming_user_is_limited_by_session_policy() {
+    let mateo = "arn:aws:iam::111122223333:user/mateo";
+    let mut scenario = scenario_with_deploy_role();
+    scenario.add_resource_policy(
+        "Drafts",
+        Policy::from_json_value(json!({
+            "Statement": [
+                {
+                    "Effect": "Allow",
+                    "Principal": { "AWS": mateo },
+                    "Action": "s3:PutObject",
+                    "Resource": "arn:aws:s3:::drafts/*"
+                }
+            ]
+        }))
+        .unwrap(),
+    );
+    let put = Request::new(
+        Principal::ARN(mateo.to_owned()),
+        "s3:PutObject",
+        "arn:aws:s3:::drafts/q3.csv",
+    );
+    assert!(scenario.eval(&put).unwrap().is_allow());
+    let decision = scenario
+        .eval(&Request {
+            session_policies: vec![SessionPolicy::Inline(put_docs_only())],
+            ..put
+        })
+        .unwrap();
+    assert_eq!(decision.outcome, Outcome::ImplicitDeny);
+    assert_eq!(decision.reason, "Not allowed by any session policy");
+}
+
+#[test]
+fn undefined_managed_session_policy_is_an_error() {
+    let scenario = scenario_with_deploy_role();
+    let policy_arn = "arn:aws:iam::111122223333:policy/NoSuchPolicy";
+    let err = scenario
+        .eval(&Request {
+            session_policies: vec![SessionPolicy::Arn(policy_arn.to_owned())],
+            ..Request::new(Principal::ARN(SESSION.to_owned()), "s3:GetObject", "*")
+        })
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains(policy_arn), "{err}");
+}
+
+#[test]
+fn session_policies_parse_from_request_json() {
+    let request = Request::from_json_value(json!({
+        "Action": "s3:GetObject",
+        "Principal": { "ARN": SESSION },
+        "Resource": "arn:aws:s3:::artifacts/app.tar.gz",
+        "SessionPolicies": [
+            READ_ONLY_ARN,
+            { "Statement": [ { "Effect": "Allow", "Action": "s3:GetObject", "Resource": "*" } ] }
+        ]
+    }))
+    .unwrap();
+    assert!(matches!(
+        request.session_policies.as_slice(),
+        [SessionPolicy::Arn(_), SessionPolicy::Inline(_)]
+    ));
+}
+
+#[test]
+fn assumed_role_session_carries_session_policies() {
+    let scenario = scenario_with_deploy_role();
+    let chain = scenario
+        .assume_role_chain(
+            Session::new(Principal::ARN(
+                "arn:aws:iam::111122223333:user/mateo".to_owned(),
+            )),
+            &[AssumeRoleRequest {
+                session_policies: vec![SessionPolicy::Inline(put_docs_only())],
+                ..AssumeRoleRequest::new("Deploy")
+            }],
+        )
+        .unwrap();
+    let session = chain.session.unwrap();
+    assert!(scenario
+        .eval(&session.request("s3:PutObject", "arn:aws:s3:::artifacts/docs/a.html"))
+        .unwrap()
+        .is_allow());
+    assert!(scenario
+        .eval(&session.request("s3:PutObject", "arn:aws:s3:::artifacts/app.tar.gz"))
+        .unwrap()
+        .is_deny());
+}
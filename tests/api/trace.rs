@@ -0,0 +1,224 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for tracing the evaluation of each statement.
+
+use indoc::indoc;
+use serde_json::json;
+
+use iamthat::json::FromJson;
+use iamthat::policy::{Policy, PolicyType};
+use iamthat::principal::Principal;
+use iamthat::scenario::Scenario;
+use iamthat::trace::{Element, ElementTrace};
+use iamthat::Request;
+
+const DEV: &str = "arn:aws:iam::111122223333:user/dev";
+
+/// The user `dev`, tagged as being on the ops team, has a policy allowing the dev team
+/// to read their own home directory.
+fn scenario() -> Scenario {
+    Scenario::from_json_value(json!({
+        "NamedPolicies": {
+            "DevTeam": {
+                "Version": "2012-10-17",
+                "Statement": [
+                    {
+                        "Sid": "ReadHome",
+                        "Effect": "Allow",
+                        "Action": "s3:GetObject",
+                        "Resource": "arn:aws:s3:::home/${aws:username}/*",
+                        "Condition": {
+                            "StringEquals": { "aws:PrincipalTag/team": "dev" }
+                        }
+                    },
+                    {
+                        "Effect": "Deny",
+                        "NotAction": "s3:Get*",
+                        "Resource": "*"
+                    }
+                ]
+            }
+        },
+        "Users": [
+            {
+                "UserName": "dev",
+                "AttachedPolicies": ["DevTeam"],
+                "Tags": [ { "Key": "team", "Value": "ops" } ]
+            }
+        ],
+        "Roles": []
+    }))
+    .unwrap()
+}
+
+#[test]
+fn trace_explains_why_a_condition_failed() {
+    let trace = scenario()
+        .trace(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:GetObject",
+            "arn:aws:s3:::home/dev/notes.txt",
+        ))
+        .unwrap();
+    assert_eq!(trace.policies.len(), 1);
+    let policy = &trace.policies[0];
+    assert_eq!(policy.policy_name, "DevTeam");
+    assert_eq!(policy.policy_type, PolicyType::Identity);
+    let statement = &policy.statements[0];
+    assert_eq!(statement.sid.as_deref(), Some("ReadHome"));
+    assert!(!statement.matched);
+    assert_eq!(
+        statement.elements,
+        [
+            ElementTrace::new(
+                Element::Action,
+                true,
+                r#""s3:GetObject" matches "s3:GetObject""#.to_owned()
+            ),
+            ElementTrace::new(
+                Element::Resource,
+                true,
+                r#""arn:aws:s3:::home/dev/notes.txt" matches "arn:aws:s3:::home/dev/*""#
+                    .to_owned()
+            ),
+            ElementTrace::new(
+                Element::Condition,
+                false,
+                r#"StringEquals aws:PrincipalTag/team: request value "ops" does not satisfy ["dev"]"#
+                    .to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn every_element_is_traced_after_a_mismatch() {
+    let trace = scenario()
+        .trace(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:PutObject",
+            "arn:aws:s3:::home/other/notes.txt",
+        ))
+        .unwrap();
+    let statements = &trace.policies[0].statements;
+    let matched = statements[0]
+        .elements
+        .iter()
+        .map(|element| (element.element, element.matched))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        matched,
+        [
+            (Element::Action, false),
+            (Element::Resource, false),
+            (Element::Condition, false)
+        ]
+    );
+    assert!(statements[1].matched);
+    assert_eq!(
+        statements[1].elements[0].explanation,
+        r#""s3:PutObject" is not excluded by NotAction ["s3:Get*"]"#
+    );
+}
+
+#[test]
+fn trace_of_missing_condition_key() {
+    let mut scenario = scenario();
+    scenario.users[0].tags.clear();
+    let trace = scenario
+        .trace(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:GetObject",
+            "arn:aws:s3:::home/dev/notes.txt",
+        ))
+        .unwrap();
+    assert_eq!(
+        trace.policies[0].statements[0].elements[2].to_string(),
+        r#"condition StringEquals aws:PrincipalTag/team: key is not present in the request, which does not satisfy ["dev"]"#
+    );
+}
+
+#[test]
+fn trace_of_principal_and_not_resource() {
+    let policy = Policy::from_json_value(json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Principal": { "AWS": "arn:aws:iam::111122223333:root" },
+                "Action": "s3:*",
+                "NotResource": "arn:aws:s3:::home/dev/*"
+            }
+        ]
+    }))
+    .unwrap();
+    let trace = policy.trace(&Request::new(
+        Principal::ARN(DEV.to_owned()),
+        "s3:GetObject",
+        "arn:aws:s3:::home/dev/notes.txt",
+    ));
+    let explanations = trace[0]
+        .elements
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        explanations,
+        [
+            r#"principal "arn:aws:iam::111122223333:user/dev" matches Principal"#,
+            r#"action "s3:GetObject" matches "s3:*""#,
+            r#"resource "arn:aws:s3:::home/dev/notes.txt" is excluded by NotResource "arn:aws:s3:::home/dev/*""#,
+        ]
+    );
+    assert!(!trace[0].matched);
+}
+
+#[test]
+fn trace_agrees_with_eval() {
+    let scenario = scenario();
+    for (action, resource) in [
+        ("s3:GetObject", "arn:aws:s3:::home/dev/notes.txt"),
+        ("s3:PutObject", "arn:aws:s3:::home/dev/notes.txt"),
+        ("s3:GetBucketPolicy", "arn:aws:s3:::home"),
+    ] {
+        let request = Request::new(Principal::ARN(DEV.to_owned()), action, resource);
+        let decision = scenario.eval(&request).unwrap();
+        let trace = scenario.trace(&request).unwrap();
+        let traced_matches = trace
+            .policies
+            .iter()
+            .flat_map(|policy| &policy.statements)
+            .filter(|statement| statement.matched)
+            .map(|statement| statement.statement_index)
+            .collect::<Vec<_>>();
+        let decided_matches = decision
+            .matched_statements
+            .iter()
+            .map(|statement| statement.statement_index)
+            .collect::<Vec<_>>();
+        assert_eq!(traced_matches, decided_matches, "{action} {resource}");
+    }
+}
+
+#[test]
+fn trace_renders_as_text() {
+    let trace = scenario()
+        .trace(&Request::new(
+            Principal::ARN(DEV.to_owned()),
+            "s3:GetObject",
+            "arn:aws:s3:::home/dev/notes.txt",
+        ))
+        .unwrap();
+    assert_eq!(
+        trace.to_string(),
+        indoc! { r#"
+            identity policy "DevTeam"
+              statement 0 ("ReadHome"), Allow: not matched
+                action "s3:GetObject" matches "s3:GetObject"
+                resource "arn:aws:s3:::home/dev/notes.txt" matches "arn:aws:s3:::home/dev/*"
+                condition StringEquals aws:PrincipalTag/team: request value "ops" does not satisfy ["dev"]
+              statement 1, Deny: not matched
+                action "s3:GetObject" is excluded by NotAction "s3:Get*"
+                resource "arn:aws:s3:::home/dev/notes.txt" matches "*"
+        "#}
+    );
+}
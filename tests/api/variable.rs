@@ -0,0 +1,235 @@
+// Copyright 2023 Martin Pool
+
+use serde_json::json;
+
+use iamthat::context::Context;
+use iamthat::json::FromJson;
+use iamthat::policy::Policy;
+use iamthat::principal::Principal;
+use iamthat::variable::expand;
+use iamthat::Request;
+
+fn context(json: serde_json::Value) -> Context {
+    serde_json::from_value(json).expect("parse context")
+}
+
+fn request(resource: &str, context: Context) -> Request {
+    Request {
+        context,
+        ..Request::new(
+            Principal::ARN("arn:aws:iam::111122223333:user/mateo".to_owned()),
+            "s3:GetObject",
+            resource,
+        )
+    }
+}
+
+fn home_directory_policy(version: Option<&str>) -> Policy {
+    let mut policy = json!({
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": "s3:GetObject",
+                "Resource": "arn:aws:s3:::home/${aws:username}/*"
+            }
+        ]
+    });
+    if let Some(version) = version {
+        policy["Version"] = json!(version);
+    }
+    Policy::from_json_value(policy).unwrap()
+}
+
+#[test]
+fn expand_variable_from_context() {
+    let context = context(json!({ "aws:username": "mateo" }));
+    let pattern = expand("arn:aws:s3:::home/${aws:username}/*", &context).unwrap();
+    assert!(pattern.matches("arn:aws:s3:::home/mateo/notes.txt"));
+    assert!(!pattern.matches("arn:aws:s3:::home/zhang/notes.txt"));
+    assert_eq!(pattern.to_string(), "arn:aws:s3:::home/mateo/*");
+}
+
+#[test]
+fn variable_keys_are_case_insensitive() {
+    let context = context(json!({ "aws:PrincipalTag/team": "dev" }));
+    let pattern = expand("${aws:principaltag/team}", &context).unwrap();
+    assert_eq!(pattern.to_string(), "dev");
+}
+
+#[test]
+fn substituted_values_are_literal() {
+    let context = context(json!({ "aws:username": "m*" }));
+    let pattern = expand("home/${aws:username}", &context).unwrap();
+    assert!(pattern.matches("home/m*"));
+    assert!(!pattern.matches("home/mateo"));
+}
+
+#[test]
+fn escapes() {
+    let pattern = expand("a${*}b${?}c${$}d*", &Context::new()).unwrap();
+    assert_eq!(pattern.to_string(), "a*b?c$d*");
+    assert!(pattern.matches("a*b?c$d-anything"));
+    assert!(!pattern.matches("axb?c$d"));
+    assert!(!pattern.matches("a*bxc$d"));
+}
+
+#[test]
+fn default_value() {
+    let text = "home/${aws:username, 'anonymous'}/";
+    assert_eq!(
+        expand(text, &Context::new()).unwrap().to_string(),
+        "home/anonymous/"
+    );
+    assert_eq!(
+        expand(text, &context(json!({ "aws:username": "mateo" })))
+            .unwrap()
+            .to_string(),
+        "home/mateo/"
+    );
+    assert_eq!(
+        expand("${aws:username,''}", &Context::new())
+            .unwrap()
+            .to_string(),
+        ""
+    );
+}
+
+#[test]
+fn unresolvable_variables() {
+    assert_eq!(expand("home/${aws:username}/", &Context::new()), None);
+    // Multi-valued keys can't be substituted.
+    assert_eq!(
+        expand(
+            "${aws:TagKeys}",
+            &context(json!({ "aws:TagKeys": ["a", "b"] }))
+        ),
+        None
+    );
+}
+
+#[test]
+fn malformed_variables_are_literal_text() {
+    assert_eq!(
+        expand("a${b c}d${e", &Context::new()).unwrap().to_string(),
+        "a${b c}d${e"
+    );
+}
+
+#[test]
+fn home_directory_resource() {
+    let policy = home_directory_policy(Some("2012-10-17"));
+    let context = context(json!({ "aws:username": "mateo" }));
+    assert!(policy.allows(&request(
+        "arn:aws:s3:::home/mateo/notes.txt",
+        context.clone()
+    )));
+    assert!(!policy.allows(&request("arn:aws:s3:::home/zhang/notes.txt", context)));
+}
+
+#[test]
+fn unresolvable_variable_in_resource_does_not_match() {
+    let policy = home_directory_policy(Some("2012-10-17"));
+    assert!(!policy.allows(&request(
+        "arn:aws:s3:::home/mateo/notes.txt",
+        Context::new()
+    )));
+}
+
+#[test]
+fn variables_are_literal_in_old_policy_versions() {
+    let context = context(json!({ "aws:username": "mateo" }));
+    for version in [None, Some("2008-10-17")] {
+        let policy = home_directory_policy(version);
+        assert!(!policy.allows(&request(
+            "arn:aws:s3:::home/mateo/notes.txt",
+            context.clone()
+        )));
+        assert!(policy.allows(&request(
+            "arn:aws:s3:::home/${aws:username}/notes.txt",
+            context.clone()
+        )));
+    }
+}
+
+#[test]
+fn statements_and_traces_follow_the_policy_version() {
+    let context = context(json!({ "aws:username": "mateo" }));
+    let literal = request(
+        "arn:aws:s3:::home/${aws:username}/notes.txt",
+        context.clone(),
+    );
+    let expanded = request("arn:aws:s3:::home/mateo/notes.txt", context);
+    for (version, expands) in [(Some("2008-10-17"), false), (Some("2012-10-17"), true)] {
+        let policy = home_directory_policy(version);
+        let statement = &policy.statement[0];
+        assert_eq!(statement.expands_variables, expands);
+        assert_eq!(statement.matches(&expanded), expands);
+        assert_eq!(statement.matches(&literal), !expands);
+        assert_eq!(statement.allows(&expanded), expands);
+        assert_eq!(policy.trace(&expanded)[0].matched, expands);
+        assert_eq!(policy.trace(&literal)[0].matched, !expands);
+    }
+}
+
+#[test]
+fn variables_in_condition_values() {
+    let policy = Policy::from_json_value(json!({
+        "Version": "2012-10-17",
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": "arn:aws:s3:::home",
+                "Condition": {
+                    "StringLike": { "s3:prefix": "${aws:username}/*" }
+                }
+            }
+        ]
+    }))
+    .unwrap();
+    let list = |prefix: &str, username: Option<&str>| {
+        let mut context = context(json!({ "s3:prefix": prefix }));
+        if let Some(username) = username {
+            context.insert("aws:username", vec![username.to_owned()]);
+        }
+        Request {
+            action: "s3:ListBucket".to_owned(),
+            ..request("arn:aws:s3:::home", context)
+        }
+    };
+    assert!(policy.allows(&list("mateo/photos", Some("mateo"))));
+    assert!(!policy.allows(&list("zhang/photos", Some("mateo"))));
+    assert!(!policy.allows(&list("mateo/photos", None)));
+}
+
+#[test]
+fn variables_in_condition_values_are_literal_in_old_policy_versions() {
+    let policy = Policy::from_json_value(json!({
+        "Version": "2008-10-17",
+        "Statement": [
+            {
+                "Effect": "Allow",
+                "Action": "s3:ListBucket",
+                "Resource": "arn:aws:s3:::home",
+                "Condition": {
+                    "StringEquals": { "s3:prefix": "${aws:username}" }
+                }
+            }
+        ]
+    }))
+    .unwrap();
+    let list = |prefix: &str| Request {
+        action: "s3:ListBucket".to_owned(),
+        ..request(
+            "arn:aws:s3:::home",
+            context(json!({ "s3:prefix": prefix, "aws:username": "mateo" })),
+        )
+    };
+    assert!(!policy.allows(&list("mateo")));
+    assert!(policy.allows(&list("${aws:username}")));
+
+    let condition = &policy.statement[0].condition;
+    let context = list("mateo").context;
+    assert!(!condition.matches(&context, policy.version.as_deref()));
+    assert!(condition.matches(&context, Some("2012-10-17")));
+}
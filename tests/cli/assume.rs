@@ -0,0 +1,53 @@
+// Copyright 2023 Martin Pool
+
+//! Tests for `iamthat assume`
+
+use assert_fs::fixture::NamedTempFile;
+use indoc::indoc;
+use predicates::prelude::*;
+
+use super::run;
+
+#[test]
+fn assume_deploy_role() {
+    let out_file = NamedTempFile::new("out.json").unwrap();
+    run()
+        .arg("assume")
+        .arg("--scenario")
+        .arg("example/scenario/deploy_role.json")
+        .arg("--principal")
+        .arg("arn:aws:iam::111122223333:user/mateo")
+        .arg("--session-name")
+        .arg("ci-1234")
+        .arg("Deploy")
+        .arg("--output")
+        .arg(out_file.path())
+        .assert()
+        .success()
+        .stdout(indoc! { "
+            arn:aws:iam::111122223333:role/ci/Deploy: Allow
+            Session: arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234
+        "});
+    let output: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(out_file.path()).unwrap()).unwrap();
+    assert_eq!(
+        output["Session"]["Principal"]["ARN"],
+        "arn:aws:sts::111122223333:assumed-role/Deploy/ci-1234"
+    );
+}
+
+#[test]
+fn untrusted_principal_is_denied() {
+    run()
+        .arg("assume")
+        .arg("--scenario")
+        .arg("example/scenario/deploy_role.json")
+        .arg("--principal")
+        .arg("arn:aws:iam::111122223333:user/zhang")
+        .arg("Deploy")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "arn:aws:iam::111122223333:role/ci/Deploy: Deny",
+        ));
+}
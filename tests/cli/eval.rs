@@ -5,6 +5,7 @@
 use assert_fs::fixture::NamedTempFile;
 use assert_fs::prelude::*;
 use indoc::indoc;
+use serde_json::{json, Value};
 
 use super::run;
 
@@ -23,7 +24,112 @@ fn s3_list_scenario_succeeds() {
         .success();
     out_file.assert(indoc! { r#"
     [
-      "Allow"
+      {
+        "Outcome": "Allow",
+        "Reason": "Allowed by a resource policy naming the principal",
+        "MatchedStatements": [
+          {
+            "PolicyName": "AllowList",
+            "PolicyType": "Resource",
+            "StatementIndex": 0,
+            "Sid": "AllowS3ListAndPut",
+            "Effect": "Allow"
+          }
+        ]
+      }
     ]
     "#});
 }
+
+#[test]
+fn trace_as_text() {
+    run()
+        .args([
+            "eval",
+            "--scenario",
+            "example/scenario/s3_list.json",
+            "--request",
+            "example/request/s3_delete_bucket.json",
+            "--trace",
+        ])
+        .assert()
+        .failure()
+        .stdout(indoc! { r#"
+            example/request/s3_delete_bucket.json: Implicit deny: Not allowed by any resource or identity policy
+            resource policy "AllowList"
+              statement 0 ("AllowS3ListAndPut"), Allow: not matched
+                principal "arn:aws:iam::111122223333:user/mateo" matches Principal
+                action "s3:DeleteBucket" does not match ["s3:List*", "s3:Put*"]
+                resource "arn:aws:s3:::production" matches "arn:aws:s3:::production"
+        "#});
+}
+
+#[test]
+fn trace_as_json() {
+    let assert = run()
+        .args([
+            "eval",
+            "--scenario",
+            "example/scenario/s3_list.json",
+            "--request",
+            "example/request/s3_list.json",
+            "--trace=json",
+        ])
+        .assert()
+        .success();
+    let traced: Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(
+        traced,
+        json!([
+            {
+                "Request": "example/request/s3_list.json",
+                "Decision": {
+                    "Outcome": "Allow",
+                    "Reason": "Allowed by a resource policy naming the principal",
+                    "MatchedStatements": [
+                        {
+                            "PolicyName": "AllowList",
+                            "PolicyType": "Resource",
+                            "StatementIndex": 0,
+                            "Sid": "AllowS3ListAndPut",
+                            "Effect": "Allow"
+                        }
+                    ]
+                },
+                "Trace": {
+                    "Policies": [
+                        {
+                            "PolicyName": "AllowList",
+                            "PolicyType": "Resource",
+                            "Statements": [
+                                {
+                                    "StatementIndex": 0,
+                                    "Sid": "AllowS3ListAndPut",
+                                    "Effect": "Allow",
+                                    "Matched": true,
+                                    "Elements": [
+                                        {
+                                            "Element": "Principal",
+                                            "Matched": true,
+                                            "Explanation": "\"arn:aws:iam::111122223333:user/mateo\" matches Principal"
+                                        },
+                                        {
+                                            "Element": "Action",
+                                            "Matched": true,
+                                            "Explanation": "\"s3:ListObjects\" matches \"s3:List*\""
+                                        },
+                                        {
+                                            "Element": "Resource",
+                                            "Matched": true,
+                                            "Explanation": "\"arn:aws:s3:::production\" matches \"arn:aws:s3:::production\""
+                                        }
+                                    ]
+                                }
+                            ]
+                        }
+                    ]
+                }
+            }
+        ])
+    );
+}
@@ -2,6 +2,7 @@
 
 //! Tests for the iamthat command line.
 
+mod assume;
 mod eval;
 mod generate_schemas;
 mod testcase;
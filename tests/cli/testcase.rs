@@ -8,6 +8,7 @@ use std::str::FromStr;
 use assert_fs::prelude::*;
 use assert_fs::NamedTempFile;
 use glob::glob;
+use indoc::indoc;
 use predicates::prelude::*;
 use serde_json::{json, Value};
 
@@ -30,6 +31,7 @@ fn s3_basics() {
             == json! {
                     [
                       [
+                        "Pass",
                         "Pass",
                         "Pass"
                       ]
@@ -77,3 +79,15 @@ fn failing_testcases() {
         assert_eq!(actual_json, expected_json);
     }
 }
+
+#[test]
+fn failed_assertions_explain_the_decision() {
+    run()
+        .args(["test", "example/failing_tests/wrong_expectations_test.json"])
+        .assert()
+        .failure()
+        .stdout(indoc! { r#"
+            example/failing_tests/wrong_expectations_test.json: assertion 0 failed: Allow: Allowed by a resource policy naming the principal: statement 0 ("AllowS3ListAndPut") of resource policy "AllowList"
+            example/failing_tests/wrong_expectations_test.json: assertion 1 failed: Implicit deny: Not allowed by any resource or identity policy
+        "#});
+}